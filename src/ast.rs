@@ -1,3 +1,6 @@
+use std::fmt;
+use std::rc::Rc;
+
 // Type annotations
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
@@ -8,19 +11,41 @@ pub enum Type {
     Custom(String),
     // Discriminated union (string literals)
     Union(Vec<String>),
+    // Optional type (value or nil), written `T?`
+    Optional(Box<Type>),
+}
+
+// Renders a type the way it is written in source
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Str => write!(f, "str"),
+            Type::Number => write!(f, "number"),
+            Type::Bool => write!(f, "bool"),
+            Type::Custom(name) => write!(f, "{}", name),
+            Type::Union(variants) => {
+                let variants: Vec<String> = variants.iter().map(|v| format!("{:?}", v)).collect();
+                write!(f, "{}", variants.join(" | "))
+            }
+            Type::Optional(inner) => write!(f, "{}?", inner),
+        }
+    }
 }
 
 // AST Node types
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
-    // Literals
-    String(String),
+    // Literals; equal string literals share one allocation
+    String(Rc<str>),
     Number(f64),
     Boolean(bool),
     Nil,
 
-    // Identifier
-    Identifier(String),
+    // Array literal
+    Array(Vec<Expr>),
+
+    // Identifier; the lexer's interned handle, shared by every use of the name
+    Identifier(Rc<str>),
 
     // Binary operations
     Binary {
@@ -39,22 +64,145 @@ pub enum Expr {
     Call {
         callee: Box<Expr>,
         arguments: Vec<Expr>,
+        named_arguments: Vec<(Rc<str>, Expr)>,
     },
 
-    // Grouping
-    Grouping(Box<Expr>),
+    // Method call: `receiver.method(args)`. It runs like `method(receiver,
+    // args)`, but the receiver must not be nil.
+    MethodCall {
+        receiver: Box<Expr>,
+        method: Rc<str>,
+        arguments: Vec<Expr>,
+        named_arguments: Vec<(Rc<str>, Expr)>,
+    },
 
     // Struct literal
     StructLiteral {
-        name: String,
-        fields: Vec<(String, Expr)>,
+        name: Rc<str>,
+        fields: Vec<(Rc<str>, Expr)>,
+    },
+
+    // Struct literal without a name, typed by the annotation it's assigned to
+    AnonymousStruct {
+        fields: Vec<(Rc<str>, Expr)>,
     },
 
     // Field access
     FieldAccess {
         object: Box<Expr>,
-        field: String,
+        field: Rc<str>,
     },
+
+    // Array spread into call arguments (e.g., f(...args))
+    Spread(Box<Expr>),
+
+    // Index access (e.g., items[0])
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+    },
+}
+
+// Source-like rendering, used in messages that quote an expression. Nested
+// operations are parenthesized rather than relying on precedence.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::String(s) => write!(f, "{:?}", s),
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Boolean(b) => write!(f, "{}", b),
+            Expr::Nil => write!(f, "nil"),
+            Expr::Array(elements) => {
+                write!(f, "[")?;
+                write_list(f, elements)?;
+                write!(f, "]")
+            }
+            Expr::Identifier(name) => write!(f, "{}", name),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                write_operand(f, left)?;
+                write!(f, " {} ", operator)?;
+                write_operand(f, right)
+            }
+            Expr::Unary { operator, operand } => {
+                write!(f, "{}", operator)?;
+                write_operand(f, operand)
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                named_arguments,
+            } => {
+                write!(f, "{}(", callee)?;
+                write_arguments(f, arguments, named_arguments)
+            }
+            Expr::MethodCall {
+                receiver,
+                method,
+                arguments,
+                named_arguments,
+            } => {
+                write_operand(f, receiver)?;
+                write!(f, ".{}(", method)?;
+                write_arguments(f, arguments, named_arguments)
+            }
+            Expr::StructLiteral { name, fields } => {
+                write!(f, "{} ", name)?;
+                write_fields(f, fields)
+            }
+            Expr::AnonymousStruct { fields } => write_fields(f, fields),
+            Expr::FieldAccess { object, field } => write!(f, "{}.{}", object, field),
+            Expr::Spread(inner) => write!(f, "...{}", inner),
+            Expr::Index { object, index } => write!(f, "{}[{}]", object, index),
+        }
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter, expr: &Expr) -> fmt::Result {
+    match expr {
+        Expr::Binary { .. } => write!(f, "({})", expr),
+        _ => write!(f, "{}", expr),
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, exprs: &[Expr]) -> fmt::Result {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", expr)?;
+    }
+    Ok(())
+}
+
+// The argument list of a call, closing paren included
+fn write_arguments(
+    f: &mut fmt::Formatter,
+    arguments: &[Expr],
+    named_arguments: &[(Rc<str>, Expr)],
+) -> fmt::Result {
+    write_list(f, arguments)?;
+    for (i, (name, value)) in named_arguments.iter().enumerate() {
+        if i > 0 || !arguments.is_empty() {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}: {}", name, value)?;
+    }
+    write!(f, ")")
+}
+
+fn write_fields(f: &mut fmt::Formatter, fields: &[(Rc<str>, Expr)]) -> fmt::Result {
+    write!(f, "{{ ")?;
+    for (i, (name, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}: {}", name, value)?;
+    }
+    write!(f, " }}")
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -69,12 +217,47 @@ pub enum BinaryOp {
     LessEqual,
     Greater,
     GreaterEqual,
+    // `&&` and `||` only evaluate their right side when it decides the result
+    And,
+    Or,
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        };
+        write!(f, "{}", symbol)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum UnaryOp {
     Negate,
     Not,
+    Plus,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "!",
+            UnaryOp::Plus => "+",
+        };
+        write!(f, "{}", symbol)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -82,15 +265,37 @@ pub enum Stmt {
     // Expression statement
     Expression(Expr),
 
-    // Let binding
-    Let { name: String, value: Expr },
+    // Let binding with an optional type annotation
+    Let {
+        name: Rc<str>,
+        type_annotation: Option<Type>,
+        value: Expr,
+    },
+
+    // Destructuring let binding
+    Destructure { pattern: Pattern, value: Expr },
+
+    // `x = value`, replacing the value of a variable declared with `let`
+    Assign { name: Rc<str>, value: Expr },
+
+    // Field assignment such as `p.x = 1` or `line.start.x = 1`; `fields` is
+    // the path from the variable `target` to the field being set
+    FieldAssign {
+        target: Rc<str>,
+        fields: Vec<Rc<str>>,
+        value: Expr,
+    },
 
     // Function declaration
     Function {
-        name: String,
-        params: Vec<(String, Option<Type>)>,
+        name: Rc<str>,
+        params: Vec<(Rc<str>, Option<Type>)>,
         return_type: Option<Type>,
         body: Vec<Stmt>,
+        // Marked `@memo`: results are cached by argument values
+        memo: bool,
+        // The last parameter, written `...name`, collects extra arguments
+        variadic: bool,
     },
 
     // If statement
@@ -106,9 +311,26 @@ pub enum Stmt {
         body: Vec<Stmt>,
     },
 
+    // For-each loop
+    For {
+        variable: Rc<str>,
+        iterable: Expr,
+        body: Vec<Stmt>,
+    },
+
+    // Match statement, arms tried in order
+    Match {
+        subject: Expr,
+        arms: Vec<MatchArm>,
+    },
+
     // Return statement
     Return(Option<Expr>),
 
+    // Loop control
+    Break,
+    Continue,
+
     // Block
     Block(Vec<Stmt>),
 
@@ -116,6 +338,8 @@ pub enum Stmt {
     Struct {
         name: String,
         fields: Vec<(String, Type)>,
+        // Fields marked `@skip_eq`, left out of `==` comparisons
+        skip_eq: Vec<String>,
     },
 
     // Type alias definition
@@ -125,6 +349,39 @@ pub enum Stmt {
     },
 }
 
+// One `pattern => body` arm of a match statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: MatchPattern,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchPattern {
+    // A literal compared with `==` (e.g., "red", 3, true, nil)
+    Literal(Expr),
+    // `_` matches anything
+    Wildcard,
+    // A name matches anything and binds the value for the arm's body
+    Binding(Rc<str>),
+}
+
+// Destructuring pattern on the left side of a `let`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    // `[first, second, ..rest]`
+    Array {
+        elements: Vec<Rc<str>>,
+        rest: Option<Rc<str>>,
+    },
+    // `Point { x, y, z = 0 }`; a field with a default may be missing from
+    // the value
+    Struct {
+        type_name: Rc<str>,
+        fields: Vec<(Rc<str>, Option<Expr>)>,
+    },
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Ast {
     pub statements: Vec<Stmt>,
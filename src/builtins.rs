@@ -0,0 +1,150 @@
+use std::fmt;
+
+// Number of arguments a built-in accepts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    // Inclusive range of accepted argument counts
+    Between(usize, usize),
+    // Some required arguments followed by any number more
+    AtLeast(usize),
+    Variadic,
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Arity::Exact(n) => count == *n,
+            Arity::Between(min, max) => (*min..=*max).contains(&count),
+            Arity::AtLeast(min) => count >= *min,
+            Arity::Variadic => true,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Exact(n) => write!(f, "{}", n),
+            Arity::Between(min, max) => write!(f, "{}-{}", min, max),
+            Arity::AtLeast(min) => write!(f, "at least {}", min),
+            Arity::Variadic => write!(f, "any"),
+        }
+    }
+}
+
+// Metadata for a function provided by the executor
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub description: &'static str,
+}
+
+// Every built-in function, in the order `weave builtins` lists them
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "print",
+        arity: Arity::Variadic,
+        description: "Print the arguments joined together, followed by a newline",
+    },
+    Builtin {
+        name: "debug",
+        arity: Arity::Exact(1),
+        description: "Print a value across lines, indenting nested structs and arrays",
+    },
+    Builtin {
+        name: "assert",
+        arity: Arity::Exact(1),
+        description: "Stop with an error quoting the condition if it is falsy",
+    },
+    Builtin {
+        name: "bool",
+        arity: Arity::Exact(1),
+        description: "Whether a value is truthy: only false and nil are not",
+    },
+    Builtin {
+        name: "len",
+        arity: Arity::Exact(1),
+        description: "Number of characters in a string, or of entries in an array, struct or map",
+    },
+    Builtin {
+        name: "byte_len",
+        arity: Arity::Exact(1),
+        description: "Number of UTF-8 bytes in a string",
+    },
+    Builtin {
+        name: "trim_start",
+        arity: Arity::Exact(1),
+        description: "String with leading whitespace removed",
+    },
+    Builtin {
+        name: "trim_end",
+        arity: Arity::Exact(1),
+        description: "String with trailing whitespace removed",
+    },
+    Builtin {
+        name: "replace",
+        arity: Arity::Exact(3),
+        description: "String with every occurrence of old replaced by new",
+    },
+    Builtin {
+        name: "range",
+        arity: Arity::Between(2, 3),
+        description: "Numbers from start up to (not including) end, by an optional step",
+    },
+    Builtin {
+        name: "sort_by",
+        arity: Arity::Exact(2),
+        description: "Array sorted by a function comparing two elements",
+    },
+    Builtin {
+        name: "approx_eq",
+        arity: Arity::Exact(3),
+        description: "Whether two numbers differ by at most the given tolerance",
+    },
+    Builtin {
+        name: "to_map",
+        arity: Arity::Exact(1),
+        description: "Map from each field name of a struct to its value",
+    },
+    Builtin {
+        name: "from_map",
+        arity: Arity::Exact(2),
+        description: "Struct of the named type built from a map of its fields",
+    },
+    Builtin {
+        name: "floor_div",
+        arity: Arity::Exact(2),
+        description: "Quotient of two numbers rounded down to a whole number",
+    },
+    Builtin {
+        name: "int",
+        arity: Arity::Exact(1),
+        description: "Number with its fractional part dropped, rounding toward zero",
+    },
+    Builtin {
+        name: "json_stringify",
+        arity: Arity::Exact(1),
+        description: "JSON text for a value; nil becomes null",
+    },
+    Builtin {
+        name: "random",
+        arity: Arity::Exact(0),
+        description: "Random number from 0 up to (not including) 1",
+    },
+    Builtin {
+        name: "random_int",
+        arity: Arity::Exact(2),
+        description: "Random integer between lo and hi, inclusive",
+    },
+    Builtin {
+        name: "seed",
+        arity: Arity::Exact(1),
+        description: "Reseed the random number generator",
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
@@ -0,0 +1,425 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Ast, BinaryOp, Expr, Stmt, UnaryOp};
+use crate::error_codes::UNDEFINED_VARIABLE;
+use crate::executor::{Executor, RuntimeError, Value, undeclared_assignment};
+use crate::scope::Scopes;
+
+// One instruction for the stack VM. Jump targets are indexes into the
+// instruction list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    // Push a literal
+    Constant(Value),
+    // Push a variable's value
+    Load(Rc<str>),
+    // Pop a value into a variable
+    Store(Rc<str>),
+    // Like `Store`, but the variable must already exist
+    Assign(Rc<str>),
+    // Pop two operands (left pushed first) and push the result
+    Binary(BinaryOp),
+    Unary(UnaryOp),
+    Jump(usize),
+    // Pop a condition and jump when it is falsy
+    JumpIfFalse(usize),
+    Pop,
+    // Pop this many values and print them joined, like `print`
+    Print(usize),
+    // Open and close a block's scope for the variables stored in it
+    EnterScope,
+    ExitScope,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Constant(Value::String(s)) => write!(f, "constant {:?}", s),
+            Op::Constant(Value::Number(n)) => write!(f, "constant {}", n),
+            Op::Constant(Value::Boolean(b)) => write!(f, "constant {}", b),
+            Op::Constant(value) => write!(f, "constant {:?}", value),
+            Op::Load(name) => write!(f, "load {}", name),
+            Op::Store(name) => write!(f, "store {}", name),
+            Op::Assign(name) => write!(f, "assign {}", name),
+            Op::Binary(op) => write!(f, "binary {}", op),
+            Op::Unary(op) => write!(f, "unary {}", op),
+            Op::Jump(target) => write!(f, "jump {:04}", target),
+            Op::JumpIfFalse(target) => write!(f, "jump_if_false {:04}", target),
+            Op::Pop => write!(f, "pop"),
+            Op::Print(count) => write!(f, "print {}", count),
+            Op::EnterScope => write!(f, "enter_scope"),
+            Op::ExitScope => write!(f, "exit_scope"),
+        }
+    }
+}
+
+// A construct the compiler doesn't handle yet; programs using it run on
+// the tree-walking executor, without `--bytecode`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bytecode error: {}", self.message)
+    }
+}
+
+// Jumps waiting for the end of the loop they break out of
+struct Loop {
+    start: usize,
+    breaks: Vec<usize>,
+    // Scopes open outside the loop body; `break` and `continue` close the
+    // rest before jumping
+    depth: usize,
+}
+
+// Compiles arithmetic, variables, `print` and control flow to a flat list of
+// instructions
+pub struct Compiler {
+    ops: Vec<Op>,
+    loops: Vec<Loop>,
+    // Scopes opened by the code compiled so far
+    depth: usize,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            ops: Vec::new(),
+            loops: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn compile(mut self, ast: &Ast) -> Result<Vec<Op>, CompileError> {
+        self.compile_statements(&ast.statements)?;
+        Ok(self.ops)
+    }
+
+    fn unsupported(what: &str) -> CompileError {
+        CompileError {
+            message: format!("{} is not supported by the bytecode compiler yet", what),
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    // Point an already emitted jump at the next instruction
+    fn patch(&mut self, jump: usize) {
+        let target = self.ops.len();
+        match &mut self.ops[jump] {
+            Op::Jump(t) | Op::JumpIfFalse(t) => *t = target,
+            _ => unreachable!("only jumps are patched"),
+        }
+    }
+
+    fn compile_statements(&mut self, statements: &[Stmt]) -> Result<(), CompileError> {
+        for stmt in statements {
+            self.compile_statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    // A block's statements, in a scope of their own
+    fn compile_block(&mut self, statements: &[Stmt]) -> Result<(), CompileError> {
+        self.emit(Op::EnterScope);
+        self.depth += 1;
+        self.compile_statements(statements)?;
+        self.depth -= 1;
+        self.emit(Op::ExitScope);
+        Ok(())
+    }
+
+    // Close the scopes opened inside the innermost loop, ahead of a jump out
+    // of its body
+    fn exit_loop_scopes(&mut self, what: &str) -> Result<usize, CompileError> {
+        let Some(current) = self.loops.last() else {
+            return Err(Self::unsupported(what));
+        };
+        for _ in current.depth..self.depth {
+            self.emit(Op::ExitScope);
+        }
+        Ok(self.loops.len() - 1)
+    }
+
+    fn compile_statement(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.compile_expression(expr)?;
+                self.emit(Op::Pop);
+            }
+            Stmt::Let {
+                name,
+                type_annotation: None,
+                value,
+            } => {
+                self.compile_expression(value)?;
+                self.emit(Op::Store(name.clone()));
+            }
+            Stmt::Let { .. } => return Err(Self::unsupported("A typed 'let'")),
+            Stmt::Assign { name, value } => {
+                self.compile_expression(value)?;
+                self.emit(Op::Assign(name.clone()));
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.compile_expression(condition)?;
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                self.compile_block(then_branch)?;
+                match else_branch {
+                    Some(else_stmts) => {
+                        let to_end = self.emit(Op::Jump(0));
+                        self.patch(to_else);
+                        self.compile_block(else_stmts)?;
+                        self.patch(to_end);
+                    }
+                    None => self.patch(to_else),
+                }
+            }
+            Stmt::While { condition, body } => {
+                let start = self.ops.len();
+                self.compile_expression(condition)?;
+                let to_end = self.emit(Op::JumpIfFalse(0));
+                self.loops.push(Loop {
+                    start,
+                    breaks: Vec::new(),
+                    depth: self.depth,
+                });
+                self.compile_block(body)?;
+                self.emit(Op::Jump(start));
+                self.patch(to_end);
+                let finished = self.loops.pop().expect("loop pushed above");
+                for jump in finished.breaks {
+                    self.patch(jump);
+                }
+            }
+            Stmt::Break => {
+                let current = self.exit_loop_scopes("'break' outside of a loop")?;
+                let jump = self.emit(Op::Jump(0));
+                self.loops[current].breaks.push(jump);
+            }
+            Stmt::Continue => {
+                let current = self.exit_loop_scopes("'continue' outside of a loop")?;
+                self.emit(Op::Jump(self.loops[current].start));
+            }
+            Stmt::Block(statements) => self.compile_block(statements)?,
+            Stmt::Destructure { .. } => return Err(Self::unsupported("Destructuring")),
+            Stmt::FieldAssign { .. } => return Err(Self::unsupported("Field assignment")),
+            Stmt::Function { .. } => return Err(Self::unsupported("A function definition")),
+            Stmt::For { .. } => return Err(Self::unsupported("A 'for' loop")),
+            Stmt::Match { .. } => return Err(Self::unsupported("'match'")),
+            Stmt::Return(_) => return Err(Self::unsupported("'return'")),
+            Stmt::Struct { .. } => return Err(Self::unsupported("A struct definition")),
+            Stmt::TypeAlias { .. } => return Err(Self::unsupported("A type alias")),
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        match expr {
+            Expr::String(s) => {
+                self.emit(Op::Constant(Value::String(s.clone())));
+            }
+            Expr::Number(n) => {
+                self.emit(Op::Constant(Value::Number(*n)));
+            }
+            Expr::Boolean(b) => {
+                self.emit(Op::Constant(Value::Boolean(*b)));
+            }
+            Expr::Nil => {
+                self.emit(Op::Constant(Value::Nil));
+            }
+            Expr::Identifier(name) => {
+                self.emit(Op::Load(name.clone()));
+            }
+            // `a && b` is `if a { bool(b) } else { false }` and `a || b` is
+            // `if a { true } else { bool(b) }`, so the right side only runs
+            // when it's needed
+            Expr::Binary {
+                left,
+                operator: operator @ (BinaryOp::And | BinaryOp::Or),
+                right,
+            } => {
+                self.compile_expression(left)?;
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                let to_end = if *operator == BinaryOp::And {
+                    self.compile_boolean(right)?;
+                    let to_end = self.emit(Op::Jump(0));
+                    self.patch(to_else);
+                    self.emit(Op::Constant(Value::Boolean(false)));
+                    to_end
+                } else {
+                    self.emit(Op::Constant(Value::Boolean(true)));
+                    let to_end = self.emit(Op::Jump(0));
+                    self.patch(to_else);
+                    self.compile_boolean(right)?;
+                    to_end
+                };
+                self.patch(to_end);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                self.emit(Op::Binary(operator.clone()));
+            }
+            Expr::Unary { operator, operand } => {
+                self.compile_expression(operand)?;
+                self.emit(Op::Unary(operator.clone()));
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                named_arguments,
+            } if matches!(callee.as_ref(), Expr::Identifier(name) if &**name == "print")
+                && named_arguments.is_empty() =>
+            {
+                for arg in arguments {
+                    self.compile_expression(arg)?;
+                }
+                self.emit(Op::Print(arguments.len()));
+                // `print` evaluates to nil
+                self.emit(Op::Constant(Value::Nil));
+            }
+            Expr::Call { callee, .. } => {
+                let what = match callee.as_ref() {
+                    Expr::Identifier(name) if &**name == "print" => "'print' with 'sep' or 'end'",
+                    _ => "Calling a function other than print",
+                };
+                return Err(Self::unsupported(what));
+            }
+            Expr::MethodCall { .. } => return Err(Self::unsupported("A method call")),
+            _ => return Err(Self::unsupported("This expression")),
+        }
+        Ok(())
+    }
+
+    // Push the truthiness of `expr` as a bool; `x || false` is `bool(x)`
+    fn compile_boolean(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        self.compile_expression(expr)?;
+        self.emit(Op::Constant(Value::Boolean(false)));
+        self.emit(Op::Binary(BinaryOp::Or));
+        Ok(())
+    }
+}
+
+// Listing for `--dump-bytecode`, one numbered instruction per line
+pub fn disassemble(ops: &[Op]) -> String {
+    ops.iter()
+        .enumerate()
+        .map(|(i, op)| format!("{:04}  {}\n", i, op))
+        .collect()
+}
+
+// Runs compiled instructions. Operators and printing go through the
+// executor's own helpers, so results match the tree-walker exactly.
+pub struct Vm {
+    ops: Vec<Op>,
+    stack: Vec<Value>,
+    variables: Scopes,
+    executor: Executor,
+}
+
+impl Vm {
+    pub fn new(ops: Vec<Op>) -> Self {
+        Vm {
+            ops,
+            stack: Vec::new(),
+            variables: Scopes::new(),
+            executor: Executor::new(Ast::new(Vec::new())),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("compiler keeps the stack balanced")
+    }
+
+    // See `Executor::with_max_output`
+    pub fn with_max_output(mut self, limit: Option<usize>) -> Self {
+        self.executor = self.executor.with_max_output(limit);
+        self
+    }
+
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        let mut pc = 0;
+        while let Some(op) = self.ops.get(pc).cloned() {
+            pc += 1;
+            match op {
+                Op::Constant(value) => self.stack.push(value),
+                Op::Load(name) => match self.variables.get(&name) {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => {
+                        return Err(RuntimeError::new(format!(
+                            "[{}] Undefined variable: {}",
+                            UNDEFINED_VARIABLE, name
+                        )));
+                    }
+                },
+                Op::Store(name) => {
+                    let value = self.pop();
+                    self.variables.insert(name, value);
+                }
+                Op::Assign(name) => {
+                    let value = self.pop();
+                    match self.variables.get_mut(&name) {
+                        Some(slot) => *slot = value,
+                        None => return Err(undeclared_assignment(&name)),
+                    }
+                }
+                Op::Binary(operator) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let result = self.executor.evaluate_binary_op(&left, &operator, &right)?;
+                    self.stack.push(result);
+                }
+                Op::Unary(operator) => {
+                    let operand = self.pop();
+                    let result = self.executor.evaluate_unary_op(&operator, &operand)?;
+                    self.stack.push(result);
+                }
+                Op::Jump(target) => pc = target,
+                Op::JumpIfFalse(target) => {
+                    let condition = self.pop();
+                    if !self.executor.is_truthy(&condition) {
+                        pc = target;
+                    }
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::EnterScope => self.variables.push(),
+                Op::ExitScope => {
+                    self.variables.pop();
+                }
+                Op::Print(count) => {
+                    let args = self.stack.split_off(self.stack.len() - count);
+                    let mut output: String = args
+                        .iter()
+                        .map(|value| self.executor.value_to_string(value))
+                        .collect();
+                    output.push('\n');
+                    self.executor.write_output(&output)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
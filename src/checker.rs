@@ -0,0 +1,872 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Ast, BinaryOp, Expr, MatchPattern, Pattern, Stmt, Type, UnaryOp};
+use crate::builtins::Arity;
+use crate::error_codes::{NON_EXHAUSTIVE_MATCH, RECURSIVE_STRUCT, UNDEFINED_VARIABLE};
+
+// Visit state for cycle detection over struct definitions
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// A problem found by the checker; only errors stop the program from running
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "Warning: {}", self.message),
+            Severity::Error => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// Static checks that run over the whole program before execution
+pub struct Checker<'a> {
+    ast: &'a Ast,
+    structs: HashMap<String, Vec<(String, Type)>>,
+    type_aliases: HashMap<String, Vec<String>>,
+    // Argument counts accepted by every definition of each function name
+    functions: HashMap<Rc<str>, Vec<Arity>>,
+    // Best-effort type of each variable, from its annotation or initializer
+    symbols: HashMap<Rc<str>, Type>,
+    // The declared type of each annotated variable in scope, which
+    // assignments must keep to
+    annotations: HashMap<Rc<str>, Type>,
+    // How many loops enclose the statement being checked
+    loop_depth: usize,
+    // Names declared in each enclosing block, innermost last
+    scopes: Vec<HashSet<Rc<str>>>,
+    // Every name bound anywhere in the program; a function body may read
+    // one bound after the definition, as long as it exists by the call
+    bound_names: HashSet<Rc<str>>,
+    // Names declared so far, including in blocks that have ended
+    declared: HashSet<Rc<str>>,
+    // How many function bodies enclose the statement being checked
+    function_depth: usize,
+    // Names defined before this program, e.g. by earlier REPL inputs
+    known: HashSet<Rc<str>>,
+    // Report warnings as errors
+    strict: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
+        Checker {
+            ast,
+            structs: HashMap::new(),
+            type_aliases: HashMap::new(),
+            functions: HashMap::new(),
+            symbols: HashMap::new(),
+            annotations: HashMap::new(),
+            loop_depth: 0,
+            scopes: vec![HashSet::new()],
+            bound_names: HashSet::new(),
+            declared: HashSet::new(),
+            function_depth: 0,
+            known: HashSet::new(),
+            strict: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn with_strict(mut self, enabled: bool) -> Self {
+        self.strict = enabled;
+        self
+    }
+
+    pub fn with_known_names<'n>(mut self, names: impl IntoIterator<Item = &'n str>) -> Self {
+        self.known.extend(names.into_iter().map(Rc::from));
+        self
+    }
+
+    // Functions defined before this program, with the argument counts each
+    // definition accepts
+    pub fn with_known_functions<'n>(
+        mut self,
+        functions: impl IntoIterator<Item = (&'n String, Vec<Arity>)>,
+    ) -> Self {
+        for (name, arities) in functions {
+            self.functions
+                .entry(Rc::from(name.as_str()))
+                .or_default()
+                .extend(arities);
+        }
+        self
+    }
+
+    // Structs and type aliases defined before this program, e.g. by earlier
+    // REPL inputs
+    pub fn with_known_types<'n>(
+        mut self,
+        structs: impl IntoIterator<Item = (&'n String, &'n [(String, Type)])>,
+        type_aliases: impl IntoIterator<Item = (&'n String, &'n [String])>,
+    ) -> Self {
+        for (name, fields) in structs {
+            self.structs.insert(name.clone(), fields.to_vec());
+        }
+        for (name, variants) in type_aliases {
+            self.type_aliases.insert(name.clone(), variants.to_vec());
+        }
+        self
+    }
+
+    pub fn check(&mut self) -> Vec<Diagnostic> {
+        let statements = &self.ast.statements;
+        self.collect_definitions(statements);
+        self.check_struct_recursion();
+        self.check_statements(statements);
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn error(&mut self, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warn(&mut self, message: String) {
+        let severity = if self.strict {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        self.diagnostics.push(Diagnostic { severity, message });
+    }
+
+    fn collect_definitions(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            match stmt {
+                Stmt::Struct { name, fields, .. } => {
+                    self.structs.insert(name.clone(), fields.clone());
+                }
+                Stmt::TypeAlias { name, variants } => {
+                    self.type_aliases.insert(name.clone(), variants.clone());
+                }
+                Stmt::Function {
+                    name,
+                    params,
+                    body,
+                    variadic,
+                    ..
+                } => {
+                    let arity = if *variadic {
+                        Arity::AtLeast(params.len() - 1)
+                    } else {
+                        Arity::Exact(params.len())
+                    };
+                    self.functions.entry(name.clone()).or_default().push(arity);
+                    self.bound_names
+                        .extend(params.iter().map(|(param, _)| param.clone()));
+                    self.collect_definitions(body);
+                }
+                Stmt::Let { name, .. } => {
+                    self.bound_names.insert(name.clone());
+                }
+                Stmt::Destructure {
+                    pattern: Pattern::Array { elements, rest },
+                    ..
+                } => {
+                    self.bound_names
+                        .extend(elements.iter().chain(rest).cloned());
+                }
+                Stmt::Destructure {
+                    pattern: Pattern::Struct { fields, .. },
+                    ..
+                } => {
+                    self.bound_names
+                        .extend(fields.iter().map(|(field, _)| field.clone()));
+                }
+                Stmt::If {
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    self.collect_definitions(then_branch);
+                    if let Some(else_stmts) = else_branch {
+                        self.collect_definitions(else_stmts);
+                    }
+                }
+                Stmt::While { body, .. } => self.collect_definitions(body),
+                Stmt::For { variable, body, .. } => {
+                    self.bound_names.insert(variable.clone());
+                    self.collect_definitions(body);
+                }
+                Stmt::Block(stmts) => self.collect_definitions(stmts),
+                Stmt::Match { arms, .. } => {
+                    for arm in arms {
+                        self.collect_definitions(&arm.body);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // A struct that contains itself (directly or through other structs) without
+    // an optional field in between would be infinitely sized.
+    fn check_struct_recursion(&mut self) {
+        let mut names: Vec<String> = self.structs.keys().cloned().collect();
+        names.sort();
+
+        let mut visits = HashMap::new();
+        for name in &names {
+            let mut path = Vec::new();
+            self.visit_struct(name, &mut visits, &mut path);
+        }
+    }
+
+    fn visit_struct(
+        &mut self,
+        name: &str,
+        visits: &mut HashMap<String, Visit>,
+        path: &mut Vec<String>,
+    ) {
+        match visits.get(name) {
+            Some(Visit::Done) => return,
+            Some(Visit::InProgress) => {
+                let start = path.iter().position(|n| n == name).unwrap_or(0);
+                let mut cycle = path[start..].to_vec();
+                cycle.push(name.to_string());
+                self.error(format!(
+                    "[{}] Recursive struct '{}' has infinite size: {} (make a field optional, e.g. '{}?')",
+                    RECURSIVE_STRUCT,
+                    name,
+                    cycle.join(" -> "),
+                    name
+                ));
+                return;
+            }
+            None => {}
+        }
+
+        visits.insert(name.to_string(), Visit::InProgress);
+        path.push(name.to_string());
+
+        let fields = self.structs.get(name).cloned().unwrap_or_default();
+        for (_, field_type) in &fields {
+            // Optional fields may be nil, so they break the cycle
+            if let Type::Custom(field_type_name) = field_type
+                && self.structs.contains_key(field_type_name)
+            {
+                self.visit_struct(field_type_name, visits, path);
+            }
+        }
+
+        path.pop();
+        visits.insert(name.to_string(), Visit::Done);
+    }
+
+    fn check_statements(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.check_statement(stmt);
+        }
+    }
+
+    // Check statements in a new scope, after declaring `names` in it
+    // Returns the names declared directly in the block
+    fn check_block<'n>(
+        &mut self,
+        names: impl IntoIterator<Item = &'n Rc<str>>,
+        body: &[Stmt],
+    ) -> HashSet<Rc<str>> {
+        let scope: HashSet<Rc<str>> = names.into_iter().cloned().collect();
+        self.declared.extend(scope.iter().cloned());
+        self.scopes.push(scope);
+        let annotations = self.annotations.clone();
+        self.check_statements(body);
+        self.annotations = annotations;
+        self.scopes.pop().expect("pushed above")
+    }
+
+    // A second `let` of a name in the same scope is probably a mistake;
+    // shadowing from a nested block is fine
+    fn declare(&mut self, name: &Rc<str>) {
+        self.declared.insert(Rc::clone(name));
+        let scope = self.scopes.last_mut().expect("checker always has a scope");
+        if !scope.insert(Rc::clone(name)) {
+            self.warn(format!(
+                "'{}' is already declared in this scope; use a new name to avoid confusion",
+                name
+            ));
+        }
+    }
+
+    fn check_statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) => self.check_expression(expr),
+            Stmt::Let {
+                name,
+                type_annotation,
+                value,
+            } => {
+                self.check_expression(value);
+                self.declare(name);
+                match type_annotation {
+                    Some(typ) => self.annotations.insert(name.clone(), typ.clone()),
+                    None => self.annotations.remove(name),
+                };
+                match type_annotation.clone().or_else(|| self.infer_type(value)) {
+                    Some(typ) => self.symbols.insert(name.clone(), typ),
+                    None => self.symbols.remove(name),
+                };
+            }
+            Stmt::Destructure { pattern, value } => {
+                self.check_expression(value);
+                match pattern {
+                    Pattern::Array { elements, rest } => {
+                        for name in elements.iter().chain(rest) {
+                            self.declare(name);
+                            self.symbols.remove(name);
+                        }
+                    }
+                    Pattern::Struct { type_name, fields } => {
+                        self.check_struct_pattern(type_name, fields)
+                    }
+                }
+            }
+            Stmt::Function {
+                name,
+                params,
+                body,
+                variadic,
+                ..
+            } => {
+                if let Some((type_name, _)) = name.split_once('.')
+                    && !self.structs.contains_key(type_name)
+                    && !self.type_aliases.contains_key(type_name)
+                {
+                    self.error(format!(
+                        "Unknown type '{}' in the definition of '{}'",
+                        type_name, name
+                    ));
+                }
+                let saved = self.symbols.clone();
+                let saved_annotations = self.annotations.clone();
+                for (index, (param, param_type)) in params.iter().enumerate() {
+                    match param_type {
+                        Some(typ) => self.symbols.insert(param.clone(), typ.clone()),
+                        None => self.symbols.remove(param),
+                    };
+                    // A `...name` parameter holds an array of its declared type
+                    match param_type {
+                        Some(typ) if !(*variadic && index + 1 == params.len()) => {
+                            self.annotations.insert(param.clone(), typ.clone())
+                        }
+                        _ => self.annotations.remove(param),
+                    };
+                }
+                // A loop around the definition doesn't enclose the body
+                let saved_depth = std::mem::replace(&mut self.loop_depth, 0);
+                self.function_depth += 1;
+                self.check_block(params.iter().map(|(param, _)| param), body);
+                self.function_depth -= 1;
+                self.loop_depth = saved_depth;
+                self.symbols = saved;
+                self.annotations = saved_annotations;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_expression(condition);
+                self.check_condition("if", condition);
+                let before = self.symbols.clone();
+                self.check_block([], then_branch);
+                let after_then = std::mem::replace(&mut self.symbols, before);
+                if let Some(else_stmts) = else_branch {
+                    self.check_block([], else_stmts);
+                }
+                self.merge_symbols(&after_then);
+            }
+            Stmt::While { condition, body } => {
+                self.check_expression(condition);
+                self.check_condition("while", condition);
+                if matches!(
+                    condition,
+                    Expr::Boolean(true) | Expr::Number(_) | Expr::String(_)
+                ) && !can_leave_loop(body, true)
+                {
+                    self.warn(
+                        "This loop never ends: its condition is always true and it has no 'break' or 'return'"
+                            .to_string(),
+                    );
+                }
+                let before = self.symbols.clone();
+                self.loop_depth += 1;
+                self.check_block([], body);
+                self.loop_depth -= 1;
+                self.merge_symbols(&before);
+            }
+            Stmt::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                self.check_expression(iterable);
+                let before = self.symbols.clone();
+                self.symbols.remove(variable);
+                self.loop_depth += 1;
+                self.check_block([variable], body);
+                self.loop_depth -= 1;
+                self.merge_symbols(&before);
+                match before.get(variable) {
+                    Some(typ) => self.symbols.insert(variable.clone(), typ.clone()),
+                    None => self.symbols.remove(variable),
+                };
+            }
+            Stmt::Match { subject, arms } => {
+                self.check_expression(subject);
+                self.check_match_exhaustive(subject, arms.iter().map(|arm| &arm.pattern));
+
+                let before = self.symbols.clone();
+                let mut after_arms = Vec::new();
+                for arm in arms {
+                    match &arm.pattern {
+                        MatchPattern::Binding(name) => {
+                            match self.infer_type(subject) {
+                                Some(typ) => self.symbols.insert(name.clone(), typ),
+                                None => self.symbols.remove(name),
+                            };
+                            self.check_block([name], &arm.body);
+                        }
+                        _ => {
+                            self.check_block([], &arm.body);
+                        }
+                    }
+                    after_arms.push(std::mem::replace(&mut self.symbols, before.clone()));
+                }
+                for after in &after_arms {
+                    self.merge_symbols(after);
+                }
+            }
+            Stmt::Return(Some(expr)) => self.check_expression(expr),
+            Stmt::Assign { name, value } => {
+                self.check_expression(value);
+                self.check_variable(name);
+                if let Some(expected) = self.annotations.get(&**name)
+                    && let Some(found) = self.infer_type(value)
+                    && self.clearly_mismatched(&found, expected)
+                {
+                    self.error(format!(
+                        "Type mismatch for variable '{}': expected {}, got {}",
+                        name, expected, found
+                    ));
+                }
+                // A value of another type leaves the variable's type unknown
+                match self.infer_type(value) {
+                    Some(typ) if self.symbols.get(&**name) == Some(&typ) => {}
+                    _ => {
+                        self.symbols.remove(&**name);
+                    }
+                }
+            }
+            Stmt::FieldAssign {
+                target,
+                fields,
+                value,
+            } => {
+                self.check_expression(value);
+                self.check_variable(target);
+                if let [field] = fields.as_slice() {
+                    self.check_field_access(target, field);
+                }
+            }
+            Stmt::Break if self.loop_depth == 0 => {
+                self.error("'break' outside of a loop".to_string());
+            }
+            Stmt::Continue if self.loop_depth == 0 => {
+                self.error("'continue' outside of a loop".to_string());
+            }
+            Stmt::Block(stmts) => {
+                // A `let` in the block shadows an outer variable only until
+                // the block ends, so the outer type comes back; assignments
+                // to outer variables still count
+                let before = self.symbols.clone();
+                for name in self.check_block([], stmts) {
+                    match before.get(&name) {
+                        Some(typ) => self.symbols.insert(name, typ.clone()),
+                        None => self.symbols.remove(&name),
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // A match over a union type alias must cover every variant or have a `_` arm
+    fn check_match_exhaustive<'p>(
+        &mut self,
+        subject: &Expr,
+        patterns: impl Iterator<Item = &'p MatchPattern>,
+    ) {
+        let Some(Type::Custom(alias)) = self.infer_type(subject) else {
+            return;
+        };
+        let Some(variants) = self.type_aliases.get(&alias).cloned() else {
+            return;
+        };
+
+        let mut covered = Vec::new();
+        for pattern in patterns {
+            match pattern {
+                MatchPattern::Wildcard | MatchPattern::Binding(_) => return,
+                MatchPattern::Literal(Expr::String(s)) if variants.iter().any(|v| **v == **s) => {
+                    covered.push(s.to_string());
+                }
+                MatchPattern::Literal(Expr::String(s)) => self.error(format!(
+                    "Match arm \"{}\" is not a variant of '{}'",
+                    s, alias
+                )),
+                MatchPattern::Literal(_) => self.error(format!(
+                    "Match arm can never match a value of '{}', whose variants are strings",
+                    alias
+                )),
+            }
+        }
+
+        let missing: Vec<String> = variants
+            .iter()
+            .filter(|v| !covered.contains(v))
+            .map(|v| format!("\"{}\"", v))
+            .collect();
+        if !missing.is_empty() {
+            self.error(format!(
+                "[{}] Non-exhaustive match over '{}': missing {} (add the arms or a '_' arm)",
+                NON_EXHAUSTIVE_MATCH,
+                alias,
+                missing.join(", ")
+            ));
+        }
+    }
+
+    // Any value is truthy or falsy at runtime, but a condition that is known
+    // not to be a bool is usually a missing comparison
+    fn check_condition(&mut self, keyword: &str, condition: &Expr) {
+        if let Some(typ) = self.infer_type(condition)
+            && typ != Type::Bool
+        {
+            self.warn(format!(
+                "'{}' condition is a {}, not a bool; write out the comparison you mean (e.g. with '!=')",
+                keyword, typ
+            ));
+        }
+    }
+
+    // After a branch that may or may not run, only keep the types both paths agree on
+    fn merge_symbols(&mut self, other: &HashMap<Rc<str>, Type>) {
+        self.symbols
+            .retain(|name, typ| other.get(name).is_some_and(|t| t == typ));
+    }
+
+    fn check_expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(name) => self.check_variable(name),
+            Expr::Array(elements) => {
+                for element in elements {
+                    self.check_expression(element);
+                }
+            }
+            Expr::Binary { left, right, .. } => {
+                self.check_expression(left);
+                self.check_expression(right);
+            }
+            Expr::Unary { operand, .. } | Expr::Spread(operand) => self.check_expression(operand),
+            Expr::Call {
+                callee,
+                arguments,
+                named_arguments,
+            } => {
+                // A callee name is looked up among functions, not variables
+                if !matches!(callee.as_ref(), Expr::Identifier(_)) {
+                    self.check_expression(callee);
+                }
+                for arg in arguments {
+                    self.check_expression(arg);
+                }
+                for (_, arg) in named_arguments {
+                    self.check_expression(arg);
+                }
+                // A variable holding a function shadows any definition
+                if let Expr::Identifier(name) = callee.as_ref()
+                    && !self.is_variable(name)
+                    && !arguments.iter().any(|arg| matches!(arg, Expr::Spread(_)))
+                {
+                    let arg_count = arguments.len() + named_arguments.len();
+                    match self.structs.get(&**name) {
+                        // Calling a struct constructs it from one value per field
+                        Some(fields)
+                            if !self.functions.contains_key(&**name)
+                                && fields.len() != arg_count =>
+                        {
+                            self.error(format!(
+                                "Struct '{}' has {} fields, got {} arguments",
+                                name,
+                                fields.len(),
+                                arg_count
+                            ));
+                        }
+                        _ => self.check_arity(name, arg_count),
+                    }
+                }
+            }
+            Expr::MethodCall {
+                receiver,
+                method,
+                arguments,
+                named_arguments,
+            } => {
+                for arg in arguments {
+                    self.check_expression(arg);
+                }
+                for (_, arg) in named_arguments {
+                    self.check_expression(arg);
+                }
+                let spread = arguments.iter().any(|arg| matches!(arg, Expr::Spread(_)));
+
+                // `Point.new(...)` names a type's associated function
+                if let Expr::Identifier(type_name) = receiver.as_ref()
+                    && self.is_type_name(type_name)
+                {
+                    let qualified = format!("{}.{}", type_name, method);
+                    if !self.functions.contains_key(qualified.as_str()) {
+                        self.error(format!(
+                            "Type '{}' has no associated function '{}'",
+                            type_name, method
+                        ));
+                    } else if !spread {
+                        self.check_arity(&qualified, arguments.len() + named_arguments.len());
+                    }
+                    return;
+                }
+
+                self.check_expression(receiver);
+                // The receiver counts as the first argument
+                if !spread {
+                    self.check_arity(method, 1 + arguments.len() + named_arguments.len());
+                }
+            }
+            Expr::StructLiteral { fields, .. } | Expr::AnonymousStruct { fields } => {
+                for (_, value) in fields {
+                    self.check_expression(value);
+                }
+            }
+            Expr::FieldAccess { object, field } => {
+                self.check_expression(object);
+                if let Expr::Identifier(name) = object.as_ref() {
+                    self.check_field_access(name, field);
+                }
+            }
+            Expr::Index { object, index } => {
+                self.check_expression(object);
+                self.check_expression(index);
+                // A literal field name can be checked like `.field`
+                if let (Expr::Identifier(name), Expr::String(field)) = (&**object, &**index)
+                    && matches!(self.symbols.get(&**name), Some(Type::Custom(_)))
+                {
+                    self.check_field_access(name, field);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_variable(&mut self, name: &str) {
+        if self.is_variable(name) {
+            return;
+        }
+        // A function referenced as a value
+        if self.functions.contains_key(name) {
+            return;
+        }
+        // Only run once it's called, by which time a later `let` may have run
+        if self.function_depth > 0 && self.bound_names.contains(name) {
+            return;
+        }
+
+        if self.declared.contains(name) {
+            self.error(format!(
+                "[{}] '{}' is not in scope here; it was declared in a block that has ended",
+                UNDEFINED_VARIABLE, name
+            ));
+        } else if self.bound_names.contains(name) {
+            self.error(format!(
+                "[{}] '{}' is used before it is declared (move its 'let' above this use)",
+                UNDEFINED_VARIABLE, name
+            ));
+        } else {
+            self.error(format!(
+                "[{}] Undefined variable: {}",
+                UNDEFINED_VARIABLE, name
+            ));
+        }
+    }
+
+    fn is_variable(&self, name: &str) -> bool {
+        self.known.contains(name) || self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    // A struct or type alias name that no variable in scope shadows
+    fn is_type_name(&self, name: &str) -> bool {
+        (self.structs.contains_key(name) || self.type_aliases.contains_key(name))
+            && !self.is_variable(name)
+    }
+
+    fn check_arity(&mut self, name: &str, arg_count: usize) {
+        let Some(arities) = self.functions.get(name) else {
+            return;
+        };
+        if arities.iter().any(|arity| arity.accepts(arg_count)) {
+            return;
+        }
+
+        let mut expected: Vec<String> = arities.iter().map(|arity| arity.to_string()).collect();
+        expected.sort();
+        expected.dedup();
+        self.error(format!(
+            "Function '{}' expects {} arguments, got {}",
+            name,
+            expected.join(" or "),
+            arg_count
+        ));
+    }
+
+    // Fields without a default must exist on the struct; the bindings take
+    // the declared field types
+    fn check_struct_pattern(&mut self, type_name: &str, fields: &[(Rc<str>, Option<Expr>)]) {
+        let Some(declared) = self.structs.get(type_name).cloned() else {
+            self.error(format!(
+                "Unknown struct '{}' in destructuring pattern",
+                type_name
+            ));
+            return;
+        };
+        for (field, default) in fields {
+            if let Some(default) = default {
+                self.check_expression(default);
+            }
+            let field_type = declared
+                .iter()
+                .find(|(f, _)| **f == **field)
+                .map(|(_, t)| t);
+            if field_type.is_none() && default.is_none() {
+                self.error(format!(
+                    "Struct '{}' has no field '{}'; give it a default, like '{} = 0'",
+                    type_name, field, field
+                ));
+            }
+            self.declare(field);
+            match field_type {
+                Some(typ) => self.symbols.insert(field.clone(), typ.clone()),
+                None => self.symbols.remove(field),
+            };
+        }
+    }
+
+    fn check_field_access(&mut self, name: &str, field: &str) {
+        let type_name = match self.symbols.get(name) {
+            Some(Type::Str) => "str",
+            Some(Type::Number) => "number",
+            Some(Type::Bool) => "bool",
+            Some(Type::Custom(struct_name)) => {
+                if let Some(fields) = self.structs.get(struct_name)
+                    && !fields.iter().any(|(f, _)| f == field)
+                {
+                    self.error(format!(
+                        "Struct '{}' has no field '{}' (accessed on '{}')",
+                        struct_name, field, name
+                    ));
+                }
+                return;
+            }
+            _ => return,
+        };
+        self.error(format!(
+            "Cannot access field '{}' on '{}', which is a {}",
+            field, name, type_name
+        ));
+    }
+
+    // Whether a value of type `found` can never be stored in a variable
+    // declared `expected`. Aliases and unions hold some strings, so only the
+    // plain types and structs are compared.
+    fn clearly_mismatched(&self, found: &Type, expected: &Type) -> bool {
+        let comparable = |typ: &Type| match typ {
+            Type::Str | Type::Number | Type::Bool => true,
+            Type::Custom(name) => self.structs.contains_key(name),
+            Type::Union(_) | Type::Optional(_) => false,
+        };
+        match expected {
+            Type::Optional(inner) => self.clearly_mismatched(found, inner),
+            _ => comparable(found) && comparable(expected) && found != expected,
+        }
+    }
+
+    fn infer_type(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::String(_) => Some(Type::Str),
+            Expr::Number(_) => Some(Type::Number),
+            Expr::Boolean(_) => Some(Type::Bool),
+            Expr::StructLiteral { name, .. } => Some(Type::Custom(name.to_string())),
+            Expr::Identifier(name) => self.symbols.get(&**name).cloned(),
+            Expr::Binary {
+                operator:
+                    BinaryOp::Equal
+                    | BinaryOp::NotEqual
+                    | BinaryOp::Less
+                    | BinaryOp::LessEqual
+                    | BinaryOp::Greater
+                    | BinaryOp::GreaterEqual
+                    | BinaryOp::And
+                    | BinaryOp::Or,
+                ..
+            } => Some(Type::Bool),
+            Expr::Unary {
+                operator: UnaryOp::Not,
+                ..
+            } => Some(Type::Bool),
+            _ => None,
+        }
+    }
+}
+
+// Whether a `break` (that ends this loop, when `own_loop` is set) or a
+// `return` can be reached from these statements
+fn can_leave_loop(statements: &[Stmt], own_loop: bool) -> bool {
+    statements.iter().any(|stmt| match stmt {
+        Stmt::Break => own_loop,
+        Stmt::Return(_) => true,
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            can_leave_loop(then_branch, own_loop)
+                || else_branch
+                    .as_ref()
+                    .is_some_and(|else_stmts| can_leave_loop(else_stmts, own_loop))
+        }
+        Stmt::Block(stmts) => can_leave_loop(stmts, own_loop),
+        Stmt::Match { arms, .. } => arms.iter().any(|arm| can_leave_loop(&arm.body, own_loop)),
+        // A `break` in a nested loop only ends that loop
+        Stmt::While { body, .. } | Stmt::For { body, .. } => can_leave_loop(body, false),
+        _ => false,
+    })
+}
@@ -1,11 +1,18 @@
+use std::fmt;
 use std::fs;
 use std::path::PathBuf;
 
 use clap::{Parser as ClapParser, Subcommand};
 
-use crate::executor::Executor;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
+use crate::repl;
+use weave::ast::Ast;
+use weave::builtins::BUILTINS;
+use weave::bytecode::{self, Compiler, Vm};
+use weave::checker::{Checker, Severity};
+use weave::error_codes;
+use weave::executor::{self, Executor};
+use weave::lexer::Lexer;
+use weave::parser::Parser;
 
 /// Weave programming language interpreter
 #[derive(ClapParser)]
@@ -24,38 +31,306 @@ pub enum Commands {
         /// Path to the .wv file to run
         #[arg(value_name = "FILE")]
         file: PathBuf,
+
+        /// Match keywords regardless of case (e.g. `Let`, `IF`)
+        #[arg(long)]
+        ignore_keyword_case: bool,
+
+        /// Seed for `random` and `random_int`, making runs reproducible
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Treat lexer and checker warnings as errors
+        #[arg(long)]
+        strict: bool,
+
+        /// Print how many times each function was called, and how many
+        /// statements it ran, when the program ends
+        #[arg(long)]
+        profile: bool,
+
+        /// Run on the bytecode VM; only arithmetic, variables, `print`,
+        /// `if` and `while` are supported so far
+        #[arg(long)]
+        bytecode: bool,
+
+        /// Print the compiled bytecode instead of running the program
+        #[arg(long)]
+        dump_bytecode: bool,
+
+        /// Report errors and warnings as JSON, one object per line on stderr
+        #[arg(long)]
+        warnings_as_json: bool,
+
+        /// Convert a number or bool returned from a `-> str` function to a
+        /// string instead of failing
+        #[arg(long)]
+        lenient_returns: bool,
+
+        /// Stop the program with an error once it has printed more than
+        /// this many bytes
+        #[arg(long, value_name = "BYTES")]
+        max_output: Option<usize>,
+    },
+    /// Start an interactive session
+    Repl,
+    /// List the built-in functions
+    Builtins,
+    /// Explain an error code, e.g. `weave explain E001`
+    Explain {
+        /// The code shown in brackets in the error message
+        #[arg(value_name = "CODE")]
+        code: String,
     },
 }
 
 impl Commands {
     pub fn execute(&self) {
         match self {
-            Commands::Run { file } => run(file),
+            Commands::Run {
+                file,
+                ignore_keyword_case,
+                seed,
+                strict,
+                profile,
+                bytecode,
+                dump_bytecode,
+                warnings_as_json,
+                lenient_returns,
+                max_output,
+            } => {
+                let options = RunOptions {
+                    ignore_keyword_case: *ignore_keyword_case,
+                    seed: *seed,
+                    strict: *strict,
+                    profile: *profile,
+                    bytecode: *bytecode,
+                    dump_bytecode: *dump_bytecode,
+                    warnings_as_json: *warnings_as_json,
+                    lenient_returns: *lenient_returns,
+                    max_output: *max_output,
+                };
+                run(file, &options)
+            }
+            Commands::Repl => repl::start(),
+            Commands::Builtins => builtins(),
+            Commands::Explain { code } => explain(code),
         }
     }
 }
 
-fn run(file: &PathBuf) {
+fn builtins() {
+    let width = BUILTINS.iter().map(|b| b.name.len()).max().unwrap_or(0);
+    for builtin in BUILTINS {
+        println!(
+            "{:<width$}  {:<5}  {}",
+            builtin.name,
+            builtin.arity.to_string(),
+            builtin.description,
+            width = width
+        );
+    }
+}
+
+fn explain(code: &str) {
+    match error_codes::lookup(code) {
+        Some(error) => {
+            println!("{}: {}", error.code, error.summary);
+            println!();
+            println!("{}", error.explanation);
+        }
+        None => {
+            eprintln!("Unknown error code '{}'", code);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Flags given to `weave run`
+struct RunOptions {
+    ignore_keyword_case: bool,
+    seed: Option<u64>,
+    strict: bool,
+    profile: bool,
+    bytecode: bool,
+    dump_bytecode: bool,
+    warnings_as_json: bool,
+    lenient_returns: bool,
+    max_output: Option<usize>,
+}
+
+// Prints a problem to stderr: `text` as is, or with `--warnings-as-json` a
+// JSON object on one line, e.g.
+//
+//   {"severity":"error","stage":"check","code":"E001","message":"Undefined variable: x","line":null,"column":null}
+//
+// `stage` is "lex", "parse", "check", "bytecode" or "runtime". `code` is
+// null when the message has none, and `line` and `column` are null when the
+// problem's position isn't known.
+fn report(
+    options: &RunOptions,
+    stage: &str,
+    severity: Severity,
+    text: &dyn fmt::Display,
+    message: &str,
+) {
+    report_at(options, stage, severity, text, message, None);
+}
+
+// `report` for a problem at a known (line, column)
+fn report_at(
+    options: &RunOptions,
+    stage: &str,
+    severity: Severity,
+    text: &dyn fmt::Display,
+    message: &str,
+    position: Option<(usize, usize)>,
+) {
+    if !options.warnings_as_json {
+        eprintln!("{}", text);
+        return;
+    }
+
+    let severity = match severity {
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    };
+    // Messages with a code start with it in brackets: "[E001] ..."
+    let (code, message) = match message
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("] "))
+    {
+        Some((code, rest)) => (executor::json_quote(code), rest),
+        None => ("null".to_string(), message),
+    };
+    let (line, column) = match position {
+        Some((line, column)) => (line.to_string(), column.to_string()),
+        None => ("null".to_string(), "null".to_string()),
+    };
+    eprintln!(
+        "{{\"severity\":\"{}\",\"stage\":\"{}\",\"code\":{},\"message\":{},\"line\":{},\"column\":{}}}",
+        severity,
+        stage,
+        code,
+        executor::json_quote(message),
+        line,
+        column
+    );
+}
+
+fn run(file: &PathBuf, options: &RunOptions) {
     let input = fs::read(file).unwrap_or_else(|err| {
         eprintln!("Error reading file '{}': {}", file.display(), err);
         std::process::exit(1);
     });
 
     // Lexer: tokenize the input bytes
-    let mut lexer = Lexer::new(input);
+    let mut lexer = Lexer::new(input).with_case_insensitive_keywords(options.ignore_keyword_case);
     let tokens = lexer.tokenize();
+    if !lexer.errors().is_empty() {
+        for error in lexer.errors() {
+            let position = Some((error.line, error.column));
+            report_at(
+                options,
+                "lex",
+                Severity::Error,
+                error,
+                &error.message,
+                position,
+            );
+        }
+        std::process::exit(1);
+    }
+    // Like checker warnings, `--strict` makes these stop the program
+    let severity = if options.strict {
+        Severity::Error
+    } else {
+        Severity::Warning
+    };
+    for warning in lexer.warnings() {
+        let position = Some((warning.line, warning.column));
+        report_at(
+            options,
+            "lex",
+            severity,
+            warning,
+            &warning.message,
+            position,
+        );
+    }
+    if options.strict && !lexer.warnings().is_empty() {
+        std::process::exit(1);
+    }
 
     // Debug: print tokens
     dbg!(&tokens);
 
     // Parser: parse tokens into AST
     let mut parser = Parser::new(tokens);
-    let ast = parser.parse();
+    let ast = parser.parse().unwrap_or_else(|errors| {
+        for error in &errors {
+            let position = Some((error.line, error.column));
+            report_at(
+                options,
+                "parse",
+                Severity::Error,
+                error,
+                &error.message,
+                position,
+            );
+        }
+        std::process::exit(1);
+    });
 
     // Debug: print AST
     dbg!(&ast);
 
+    // Checker: reject invalid programs before running them
+    let diagnostics = Checker::new(&ast).with_strict(options.strict).check();
+    for diagnostic in &diagnostics {
+        let message = &diagnostic.message;
+        report(options, "check", diagnostic.severity, diagnostic, message);
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+
+    if options.bytecode || options.dump_bytecode {
+        run_bytecode(&ast, options);
+        return;
+    }
+
     // Executor: execute the AST
-    let mut executor = Executor::new(ast);
-    executor.exec();
+    let mut executor = Executor::new(ast)
+        .with_profile(options.profile)
+        .with_lenient_returns(options.lenient_returns)
+        .with_max_output(options.max_output);
+    if let Some(seed) = options.seed {
+        executor = executor.with_seed(seed);
+    }
+    let result = executor.exec();
+    // Reported even when the program failed, to show how far it got
+    if let Some(report) = executor.profile_report() {
+        eprint!("{}", report);
+    }
+    if let Err(err) = result {
+        report(options, "runtime", Severity::Error, &err, &err.message);
+        std::process::exit(1);
+    }
+}
+
+fn run_bytecode(ast: &Ast, options: &RunOptions) {
+    let ops = Compiler::new().compile(ast).unwrap_or_else(|err| {
+        report(options, "bytecode", Severity::Error, &err, &err.message);
+        std::process::exit(1);
+    });
+    if options.dump_bytecode {
+        print!("{}", bytecode::disassemble(&ops));
+        return;
+    }
+
+    if let Err(err) = Vm::new(ops).with_max_output(options.max_output).run() {
+        report(options, "runtime", Severity::Error, &err, &err.message);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,90 @@
+// A stable error code with a longer explanation for `weave explain`
+#[derive(Debug)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub summary: &'static str,
+    pub explanation: &'static str,
+}
+
+pub const UNDEFINED_VARIABLE: &str = "E001";
+pub const UNDEFINED_FUNCTION: &str = "E002";
+pub const RECURSIVE_STRUCT: &str = "E003";
+pub const NON_EXHAUSTIVE_MATCH: &str = "E004";
+
+// Every error code, in the order they were introduced
+pub const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: UNDEFINED_VARIABLE,
+        summary: "A variable was used that has no value in scope",
+        explanation: "\
+A name was read before any `let` bound it, or after the scope that bound
+it ended (for example, a `for` loop variable used after the loop).
+
+    print(total)       # error: total was never defined
+
+Define the variable first:
+
+    let total = 0
+    print(total)",
+    },
+    ErrorCode {
+        code: UNDEFINED_FUNCTION,
+        summary: "A function was called that is neither defined nor built in",
+        explanation: "\
+The callee isn't a function defined with `fn` (that is still in scope)
+or one of the built-ins listed by `weave builtins`.
+
+    greet(\"ada\")       # error: no `fn greet` exists
+
+Define the function before calling it:
+
+    fn greet(name: str) {
+        print(\"Hello, \", name)
+    }
+    greet(\"ada\")",
+    },
+    ErrorCode {
+        code: RECURSIVE_STRUCT,
+        summary: "A struct contains itself and would be infinitely sized",
+        explanation: "\
+A struct refers to itself, directly or through other structs, without an
+optional field in between, so a value of it could never be finished.
+
+    struct Node {
+        next: Node         # error: every Node needs another Node
+    }
+
+Make one of the fields in the cycle optional so it can end with nil:
+
+    struct Node {
+        next: Node?
+    }",
+    },
+    ErrorCode {
+        code: NON_EXHAUSTIVE_MATCH,
+        summary: "A match over a union type doesn't cover every variant",
+        explanation: "\
+When the matched value's type is a union alias, each variant needs an arm
+so that no value falls through.
+
+    type Light = \"red\" | \"green\"
+    fn go(light: Light) {
+        match light {
+            \"green\" => return true,   # error: \"red\" is missing
+        }
+    }
+
+Add the missing arms, or a `_` arm to handle the rest:
+
+    match light {
+        \"green\" => return true,
+        _ => return false,
+    }",
+    },
+];
+
+pub fn lookup(code: &str) -> Option<&'static ErrorCode> {
+    ERROR_CODES
+        .iter()
+        .find(|error| error.code.eq_ignore_ascii_case(code))
+}
@@ -1,22 +1,112 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
 
-use crate::ast::{Ast, BinaryOp, Expr, Stmt, Type, UnaryOp};
+use crate::ast::{Ast, BinaryOp, Expr, MatchArm, MatchPattern, Pattern, Stmt, Type, UnaryOp};
+use crate::builtins;
+use crate::error_codes::{UNDEFINED_FUNCTION, UNDEFINED_VARIABLE};
+use crate::random::Rng;
+use crate::scope::{Scopes, add_overload};
+
+// Most elements `range` builds into an array
+const MAX_RANGE_LEN: u64 = 10_000_000;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
-    String(String),
+    // Shared, so copying a string value (or a literal) doesn't allocate
+    String(Rc<str>),
     Number(f64),
     Boolean(bool),
     Nil,
+    Array(Vec<Value>),
     Struct {
         type_name: String,
         fields: HashMap<String, Value>,
     },
+    // A function referenced by name, e.g. `let f = add`
+    Function(Rc<Function>),
+    // String keys to values, kept sorted by key; made by `to_map`
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    // The language's name for this value's type: `str`, `number`, `bool`,
+    // `nil`, `array`, `fn`, `map` or a struct's name
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::String(_) => "str",
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "bool",
+            Value::Nil => "nil",
+            Value::Array(_) => "array",
+            Value::Struct { type_name, .. } => type_name,
+            Value::Function(_) => "fn",
+            Value::Map(_) => "map",
+        }
+    }
+
+    // The type name, followed by the value itself when it is short, e.g.
+    // `number 3` or `str "thin"`; used in type mismatch errors
+    fn describe(&self) -> String {
+        match self {
+            Value::String(s) => format!("str {:?}", s),
+            Value::Number(n) => format!("number {}", n),
+            Value::Boolean(b) => format!("bool {}", b),
+            other => other.type_name().to_string(),
+        }
+    }
+}
+
+// The arguments a builtin was given, for its error message, e.g.
+// `number 3, str "x"`
+fn describe_args(args: &[Value]) -> String {
+    if args.is_empty() {
+        return "no arguments".to_string();
+    }
+    let described: Vec<String> = args.iter().map(Value::describe).collect();
+    described.join(", ")
+}
+
+// An error that stops the program
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    // Functions the error passed through on its way out, innermost first
+    pub stack: Vec<String>,
+}
+
+impl RuntimeError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        RuntimeError {
+            message: message.into(),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Runtime error: {}", self.message)?;
+        for function in &self.stack {
+            write!(f, "\n  in {}", function)?;
+        }
+        Ok(())
+    }
+}
+
+// How a statement left the normal flow of execution
+#[derive(Debug, Clone)]
+enum ControlFlow {
+    Return(Value),
+    Break,
+    Continue,
 }
 
 #[derive(Debug, Clone)]
 struct StructDef {
     fields: Vec<(String, Type)>,
+    skip_eq: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -24,308 +114,1720 @@ struct TypeAlias {
     variants: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
-struct Function {
-    params: Vec<(String, Option<Type>)>,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    name: String,
+    params: Vec<(Rc<str>, Option<Type>)>,
     return_type: Option<Type>,
     body: Vec<Stmt>,
+    memo: bool,
+    // The last parameter collects extra positional arguments into an array
+    variadic: bool,
+    // Variables from the blocks and call around the definition, as they were
+    // when it ran; e.g. each loop iteration's variable for a function defined
+    // in the loop
+    captured: Vec<(Rc<str>, Value)>,
+}
+
+impl Function {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Whether a new definition replaces this one rather than overloading it
+    pub(crate) fn same_signature(&self, other: &Function) -> bool {
+        self.params.len() == other.params.len()
+            && self.variadic == other.variadic
+            && self.receiver_type() == other.receiver_type()
+    }
+
+    // Whether a call with this many arguments fits the parameter list
+    fn accepts(&self, count: usize) -> bool {
+        if self.variadic {
+            count + 1 >= self.params.len()
+        } else {
+            count == self.params.len()
+        }
+    }
+
+    // The argument counts this definition accepts
+    fn arity(&self) -> builtins::Arity {
+        if self.variadic {
+            builtins::Arity::AtLeast(self.params.len() - 1)
+        } else {
+            builtins::Arity::Exact(self.params.len())
+        }
+    }
+
+    // The type named by the first parameter, which method calls dispatch on
+    fn receiver_type(&self) -> Option<&str> {
+        match self.params.first() {
+            Some((_, Some(Type::Custom(type_name)))) => Some(type_name),
+            _ => None,
+        }
+    }
+}
+
+// An argument of a `@memo` call as a cache key. Each variant keeps the
+// value's type, so `1` and `"1"` never share a result, and struct fields
+// are sorted so their order doesn't matter.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MemoKey {
+    String(Rc<str>),
+    // The bits of the number, with -0 the same as 0
+    Number(u64),
+    Boolean(bool),
+    Nil,
+    Array(Vec<MemoKey>),
+    Struct(String, Vec<(String, MemoKey)>),
+    Map(Vec<(String, MemoKey)>),
+    Function(FunctionKey),
+}
+
+impl MemoKey {
+    fn new(value: &Value) -> Self {
+        match value {
+            Value::String(s) => MemoKey::String(Rc::clone(s)),
+            Value::Number(n) if *n == 0.0 => MemoKey::Number(0.0_f64.to_bits()),
+            Value::Number(n) => MemoKey::Number(n.to_bits()),
+            Value::Boolean(b) => MemoKey::Boolean(*b),
+            Value::Nil => MemoKey::Nil,
+            Value::Array(items) => MemoKey::Array(items.iter().map(MemoKey::new).collect()),
+            Value::Struct { type_name, fields } => {
+                let mut fields: Vec<(String, MemoKey)> = fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), MemoKey::new(value)))
+                    .collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                MemoKey::Struct(type_name.clone(), fields)
+            }
+            Value::Map(entries) => MemoKey::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), MemoKey::new(value)))
+                    .collect(),
+            ),
+            Value::Function(func) => MemoKey::Function(FunctionKey(Rc::clone(func))),
+        }
+    }
+}
+
+// A function compared by identity; holding it keeps the address from being
+// reused by another function while the key is cached
+#[derive(Debug, Clone)]
+struct FunctionKey(Rc<Function>);
+
+impl PartialEq for FunctionKey {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for FunctionKey {}
+
+impl std::hash::Hash for FunctionKey {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).hash(state);
+    }
+}
+
+// Per-function counts gathered with `--profile`
+#[derive(Debug, Clone, Default)]
+struct FunctionStats {
+    calls: u64,
+    // Statements run directly in the body, not counting callees
+    statements: u64,
+}
+
+#[derive(Debug, Default)]
+struct Profile {
+    stats: HashMap<String, FunctionStats>,
+    // Functions being called, innermost last
+    stack: Vec<String>,
 }
 
 pub struct Executor {
     ast: Ast,
-    variables: HashMap<String, Value>,
-    functions: HashMap<String, Function>,
+    variables: Scopes,
+    // Every definition of each function name; they differ in parameter
+    // count or in the type of their first parameter
+    functions: HashMap<String, Vec<Rc<Function>>>,
     structs: HashMap<String, StructDef>,
     type_aliases: HashMap<String, TypeAlias>,
+    rng: Rng,
+    // Results of `@memo` functions, keyed by function name and arguments
+    memo_cache: HashMap<(String, Vec<MemoKey>), Value>,
+    profile: Option<Profile>,
+    // Convert returned numbers and bools to `str` when that is the declared
+    // return type, instead of failing
+    lenient_returns: bool,
+    // Bytes the program may print before it is stopped, and how many it has
+    max_output: Option<usize>,
+    output_written: usize,
 }
 
 impl Executor {
     pub fn new(ast: Ast) -> Self {
         Executor {
             ast,
-            variables: HashMap::new(),
+            variables: Scopes::new(),
             functions: HashMap::new(),
             structs: HashMap::new(),
             type_aliases: HashMap::new(),
+            rng: Rng::from_time(),
+            memo_cache: HashMap::new(),
+            profile: None,
+            lenient_returns: false,
+            max_output: None,
+            output_written: 0,
+        }
+    }
+
+    // Makes `random` and `random_int` reproducible across runs
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    // Count calls and statements per user function, see `profile_report`
+    pub fn with_profile(mut self, enabled: bool) -> Self {
+        self.profile = enabled.then(Profile::default);
+        self
+    }
+
+    pub fn with_lenient_returns(mut self, enabled: bool) -> Self {
+        self.lenient_returns = enabled;
+        self
+    }
+
+    // Stop the program once it has printed more than `limit` bytes
+    pub fn with_max_output(mut self, limit: Option<usize>) -> Self {
+        self.max_output = limit;
+        self
+    }
+
+    // Print `text` to stdout, counting it against `max_output`
+    pub(crate) fn write_output(&mut self, text: &str) -> Result<(), RuntimeError> {
+        self.output_written += text.len();
+        if let Some(limit) = self.max_output
+            && self.output_written > limit
+        {
+            return Err(RuntimeError::new(format!(
+                "Output limit of {} bytes exceeded",
+                limit
+            )));
+        }
+        print!("{}", text);
+        // Flush so output shows up before anything reads stdin
+        io::stdout().flush().ok();
+        Ok(())
+    }
+
+    // Table of calls and statements per function, most called first; `None`
+    // unless profiling is on
+    pub fn profile_report(&self) -> Option<String> {
+        let profile = self.profile.as_ref()?;
+        let mut rows: Vec<(&String, &FunctionStats)> = profile.stats.iter().collect();
+        rows.sort_by(|(a_name, a), (b_name, b)| b.calls.cmp(&a.calls).then(a_name.cmp(b_name)));
+
+        let width = rows
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(["function".len()])
+            .max()
+            .unwrap_or(0);
+        let mut report = format!(
+            "{:<width$}  {:>10}  {:>10}\n",
+            "function", "calls", "statements"
+        );
+        for (name, stats) in rows {
+            report.push_str(&format!(
+                "{:<width$}  {:>10}  {:>10}\n",
+                name, stats.calls, stats.statements
+            ));
         }
+        Some(report)
     }
 
-    pub fn exec(&mut self) {
+    pub fn exec(&mut self) -> Result<(), RuntimeError> {
         let statements = self.ast.statements.clone();
+        self.register_types(&statements)?;
         for statement in &statements {
-            self.execute_statement(statement);
+            self.execute_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    // Names of the variables defined so far
+    pub fn variable_names(&self) -> impl Iterator<Item = &str> {
+        self.variables.names()
+    }
+
+    // The variables defined so far with their current values
+    pub fn variables(&self) -> Vec<(&str, &Value)> {
+        self.variables.visible()
+    }
+
+    // A function's definitions, innermost first: one defined in a running
+    // function body, then the top-level ones
+    fn function_overloads(&self, name: &str) -> Option<&Vec<Rc<Function>>> {
+        self.variables
+            .function(name)
+            .or_else(|| self.functions.get(name))
+    }
+
+    // The functions defined so far with the argument counts of each definition
+    pub fn function_arities(&self) -> impl Iterator<Item = (&String, Vec<builtins::Arity>)> {
+        self.functions
+            .iter()
+            .map(|(name, overloads)| (name, overloads.iter().map(|func| func.arity()).collect()))
+    }
+
+    // The structs defined so far with their fields
+    pub fn struct_fields(&self) -> impl Iterator<Item = (&String, &[(String, Type)])> {
+        self.structs
+            .iter()
+            .map(|(name, def)| (name, def.fields.as_slice()))
+    }
+
+    // The type aliases defined so far with their variants
+    pub fn type_alias_variants(&self) -> impl Iterator<Item = (&String, &[String])> {
+        self.type_aliases
+            .iter()
+            .map(|(name, alias)| (name, alias.variants.as_slice()))
+    }
+
+    // Run statements on top of the existing state, returning the value of a
+    // trailing expression statement so the REPL can echo it
+    pub fn exec_repl(&mut self, ast: Ast) -> Result<Option<Value>, RuntimeError> {
+        self.register_types(&ast.statements)?;
+        let mut last_value = None;
+        for statement in &ast.statements {
+            last_value = match statement {
+                Stmt::Expression(expr) => Some(self.evaluate_expression(expr)?),
+                _ => {
+                    self.execute_statement(statement)?;
+                    None
+                }
+            };
         }
+        Ok(last_value)
     }
 
-    fn execute_statement(&mut self, stmt: &Stmt) -> Option<Value> {
+    // Define top-level structs and type aliases before anything runs, so they
+    // can be used above their definition and refer to each other
+    fn register_types(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        for stmt in statements {
+            if matches!(stmt, Stmt::Struct { .. } | Stmt::TypeAlias { .. }) {
+                self.execute_statement(stmt)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_statement(&mut self, stmt: &Stmt) -> Result<Option<ControlFlow>, RuntimeError> {
+        if let Some(profile) = &mut self.profile
+            && let Some(current) = profile.stack.last()
+            && let Some(stats) = profile.stats.get_mut(current)
+        {
+            stats.statements += 1;
+        }
+
         match stmt {
             Stmt::Expression(expr) => {
-                self.evaluate_expression(expr);
-                None
+                self.evaluate_expression(expr)?;
+                Ok(None)
+            }
+            Stmt::Let {
+                name,
+                type_annotation,
+                value,
+            } => {
+                let result = self.evaluate_with_expected(value, type_annotation.as_ref())?;
+                if let Some(expected_type) = type_annotation
+                    && !self.type_matches(&result, expected_type)
+                {
+                    return Err(RuntimeError::new(format!(
+                        "Type mismatch for variable '{}': expected {}, got {}",
+                        name,
+                        expected_type,
+                        result.describe()
+                    )));
+                }
+                match type_annotation {
+                    Some(typ) => self
+                        .variables
+                        .insert_typed(name.clone(), result, typ.clone()),
+                    None => self.variables.insert(name.clone(), result),
+                }
+                Ok(None)
+            }
+            Stmt::Destructure { pattern, value } => {
+                let result = self.evaluate_expression(value)?;
+                self.bind_pattern(pattern, result)?;
+                Ok(None)
             }
-            Stmt::Let { name, value } => {
-                let result = self.evaluate_expression(value);
-                self.variables.insert(name.clone(), result);
-                None
+            Stmt::Assign { name, value } => {
+                // A variable declared with a type keeps it
+                let declared = self.variables.declared_type(name).cloned();
+                let value = self.evaluate_with_expected(value, declared.as_ref())?;
+                if let Some(expected_type) = &declared
+                    && !self.type_matches(&value, expected_type)
+                {
+                    return Err(RuntimeError::new(format!(
+                        "Type mismatch for variable '{}': expected {}, got {}",
+                        name,
+                        expected_type,
+                        value.describe()
+                    )));
+                }
+                match self.variables.get_mut(name) {
+                    Some(slot) => *slot = value,
+                    None => return Err(undeclared_assignment(name)),
+                }
+                Ok(None)
+            }
+            Stmt::FieldAssign {
+                target,
+                fields,
+                value,
+            } => {
+                let value = self.evaluate_expression(value)?;
+                let Some(mut object) = self.variables.get(target).cloned() else {
+                    return Err(RuntimeError::new(format!(
+                        "[{}] Undefined variable: {}",
+                        UNDEFINED_VARIABLE, target
+                    )));
+                };
+                self.assign_field(&mut object, fields, value)?;
+                if let Some(slot) = self.variables.get_mut(target) {
+                    *slot = object;
+                }
+                Ok(None)
             }
             Stmt::Function {
                 name,
                 params,
                 return_type,
                 body,
+                memo,
+                variadic,
             } => {
                 let func = Function {
+                    name: name.to_string(),
                     params: params.clone(),
                     return_type: return_type.clone(),
                     body: body.clone(),
+                    memo: *memo,
+                    variadic: *variadic,
+                    captured: self.variables.locals(),
                 };
-                self.functions.insert(name.clone(), func);
-                None
+                // A redefinition must not answer from the old body's results
+                self.memo_cache.retain(|(cached, _), _| **cached != **name);
+                // One defined in a function body lasts until that call returns
+                if self.variables.in_frame() {
+                    self.variables.define_function(Rc::new(func));
+                } else {
+                    let overloads = self.functions.entry(name.to_string()).or_default();
+                    add_overload(overloads, Rc::new(func));
+                }
+                Ok(None)
             }
             Stmt::If {
                 condition,
                 then_branch,
                 else_branch,
             } => {
-                let cond_result = self.evaluate_expression(condition);
+                let cond_result = self.evaluate_expression(condition)?;
                 if self.is_truthy(&cond_result) {
-                    for stmt in then_branch {
-                        if let Some(return_val) = self.execute_statement(stmt) {
-                            return Some(return_val);
-                        }
-                    }
+                    self.execute_block(then_branch)
                 } else if let Some(else_stmts) = else_branch {
-                    for stmt in else_stmts {
-                        if let Some(return_val) = self.execute_statement(stmt) {
-                            return Some(return_val);
-                        }
-                    }
+                    self.execute_block(else_stmts)
+                } else {
+                    Ok(None)
                 }
-                None
             }
             Stmt::While { condition, body } => {
                 loop {
-                    let cond_result = self.evaluate_expression(condition);
+                    let cond_result = self.evaluate_expression(condition)?;
                     if !self.is_truthy(&cond_result) {
                         break;
                     }
-                    for stmt in body {
-                        if let Some(return_val) = self.execute_statement(stmt) {
-                            return Some(return_val);
-                        }
+                    match self.execute_block(body)? {
+                        Some(ControlFlow::Break) => break,
+                        Some(ControlFlow::Continue) | None => {}
+                        Some(flow) => return Ok(Some(flow)),
                     }
                 }
-                None
+                Ok(None)
             }
+            Stmt::For {
+                variable,
+                iterable,
+                body,
+            } => self.execute_for(variable, iterable, body),
+            Stmt::Match { subject, arms } => self.execute_match(subject, arms),
             Stmt::Return(value) => {
                 if let Some(expr) = value {
-                    let result = self.evaluate_expression(expr);
-                    Some(result)
+                    let result = self.evaluate_expression(expr)?;
+                    Ok(Some(ControlFlow::Return(result)))
                 } else {
-                    Some(Value::Nil)
-                }
-            }
-            Stmt::Block(statements) => {
-                for stmt in statements {
-                    if let Some(return_val) = self.execute_statement(stmt) {
-                        return Some(return_val);
-                    }
+                    Ok(Some(ControlFlow::Return(Value::Nil)))
                 }
-                None
             }
-            Stmt::Struct { name, fields } => {
+            Stmt::Break => Ok(Some(ControlFlow::Break)),
+            Stmt::Continue => Ok(Some(ControlFlow::Continue)),
+            Stmt::Block(statements) => self.execute_block(statements),
+            Stmt::Struct {
+                name,
+                fields,
+                skip_eq,
+            } => {
                 let struct_def = StructDef {
                     fields: fields.clone(),
+                    skip_eq: skip_eq.clone(),
                 };
                 self.structs.insert(name.clone(), struct_def);
-                None
+                Ok(None)
             }
             Stmt::TypeAlias { name, variants } => {
                 let type_alias = TypeAlias {
                     variants: variants.clone(),
                 };
                 self.type_aliases.insert(name.clone(), type_alias);
-                None
+                Ok(None)
             }
         }
     }
 
-    fn evaluate_expression(&mut self, expr: &Expr) -> Value {
+    fn execute_match(
+        &mut self,
+        subject: &Expr,
+        arms: &[MatchArm],
+    ) -> Result<Option<ControlFlow>, RuntimeError> {
+        let value = self.evaluate_expression(subject)?;
+        for arm in arms {
+            let matched = match &arm.pattern {
+                MatchPattern::Wildcard => true,
+                MatchPattern::Literal(literal) => {
+                    let literal = self.evaluate_expression(literal)?;
+                    self.values_equal(&literal, &value)
+                }
+                MatchPattern::Binding(name) => {
+                    // Bound only for this arm, like a loop variable
+                    self.variables.push();
+                    self.variables.insert(name.clone(), value);
+                    let result = self.execute_body(&arm.body);
+                    self.variables.pop();
+                    return result;
+                }
+            };
+            if matched {
+                return self.execute_block(&arm.body);
+            }
+        }
+
+        Err(RuntimeError::new(format!(
+            "No match arm for value {}",
+            value.describe()
+        )))
+    }
+
+    fn execute_for(
+        &mut self,
+        variable: &Rc<str>,
+        iterable: &Expr,
+        body: &[Stmt],
+    ) -> Result<Option<ControlFlow>, RuntimeError> {
+        // `for x in range(a, b)` counts directly instead of building the array
+        let mut lazy_range = None;
+        if let Expr::Call {
+            callee,
+            arguments,
+            named_arguments,
+        } = iterable
+            && matches!(callee.as_ref(), Expr::Identifier(name) if &**name == "range")
+            && named_arguments.is_empty()
+            && self.function_overloads("range").is_none()
+            && !matches!(self.variables.get("range"), Some(Value::Function(_)))
+        {
+            let arg_values = self.evaluate_arguments(arguments, &[])?;
+            lazy_range = Some(self.range_steps(&arg_values)?);
+        }
+
+        let items = match lazy_range {
+            Some(_) => Vec::new(),
+            None => match self.evaluate_expression(iterable)? {
+                Value::Array(items) => items,
+                other => {
+                    return Err(RuntimeError::new(format!(
+                        "Cannot iterate over non-array value: {}",
+                        other.describe()
+                    )));
+                }
+            },
+        };
+
+        let values: Box<dyn Iterator<Item = Value>> = match lazy_range {
+            Some((start, step, count)) => {
+                Box::new((0..count).map(move |i| Value::Number(start + i as f64 * step)))
+            }
+            None => Box::new(items.into_iter()),
+        };
+        let mut result = Ok(None);
+        for item in values {
+            result = self.execute_iteration(variable, item, body);
+            if !matches!(result, Ok(None)) {
+                break;
+            }
+        }
+
+        // `break` only ends this loop
+        Ok(result?.filter(|flow| !matches!(flow, ControlFlow::Break)))
+    }
+
+    // Runs one loop iteration; `None` means keep going. The loop variable is
+    // bound fresh in the iteration's own scope, so it doesn't outlive the loop.
+    fn execute_iteration(
+        &mut self,
+        variable: &Rc<str>,
+        item: Value,
+        body: &[Stmt],
+    ) -> Result<Option<ControlFlow>, RuntimeError> {
+        self.variables.push();
+        self.variables.insert(Rc::clone(variable), item);
+        let result = self.execute_body(body);
+        self.variables.pop();
+        match result? {
+            Some(ControlFlow::Continue) => Ok(None),
+            flow => Ok(flow),
+        }
+    }
+
+    // Runs statements in a new scope, which is dropped even if one fails
+    fn execute_block(&mut self, body: &[Stmt]) -> Result<Option<ControlFlow>, RuntimeError> {
+        self.variables.push();
+        let result = self.execute_body(body);
+        self.variables.pop();
+        result
+    }
+
+    fn execute_body(&mut self, body: &[Stmt]) -> Result<Option<ControlFlow>, RuntimeError> {
+        for stmt in body {
+            if let Some(flow) = self.execute_statement(stmt)? {
+                return Ok(Some(flow));
+            }
+        }
+        Ok(None)
+    }
+
+    fn bind_pattern(&mut self, pattern: &Pattern, value: Value) -> Result<(), RuntimeError> {
+        match pattern {
+            Pattern::Array { elements, rest } => {
+                let items = match value {
+                    Value::Array(items) => items,
+                    other => {
+                        return Err(RuntimeError::new(format!(
+                            "Cannot destructure non-array value: {}",
+                            other.describe()
+                        )));
+                    }
+                };
+
+                let length_matches = match rest {
+                    Some(_) => items.len() >= elements.len(),
+                    None => items.len() == elements.len(),
+                };
+                if !length_matches {
+                    return Err(RuntimeError::new(format!(
+                        "Array pattern expects {}{} elements, got {}",
+                        if rest.is_some() { "at least " } else { "" },
+                        elements.len(),
+                        items.len()
+                    )));
+                }
+
+                let mut items = items.into_iter();
+                for name in elements {
+                    let item = items.next().unwrap_or(Value::Nil);
+                    self.variables.insert(name.clone(), item);
+                }
+                if let Some(rest_name) = rest {
+                    self.variables
+                        .insert(rest_name.clone(), Value::Array(items.collect()));
+                }
+                Ok(())
+            }
+            Pattern::Struct { type_name, fields } => {
+                let mut values = match value {
+                    Value::Struct {
+                        type_name: actual,
+                        fields,
+                    } if actual.as_str() == &**type_name => fields,
+                    other => {
+                        return Err(RuntimeError::new(format!(
+                            "Cannot destructure {} with a {} pattern",
+                            other.describe(),
+                            type_name
+                        )));
+                    }
+                };
+
+                // A default is only evaluated when its field is missing
+                for (field, default) in fields {
+                    let value = match (values.remove(&**field), default) {
+                        (Some(value), _) => value,
+                        (None, Some(default)) => self.evaluate_expression(default)?,
+                        (None, None) => {
+                            return Err(RuntimeError::new(format!(
+                                "Struct '{}' has no field '{}'",
+                                type_name, field
+                            )));
+                        }
+                    };
+                    self.variables.insert(field.clone(), value);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn evaluate_expression(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
         match expr {
-            Expr::String(s) => Value::String(s.clone()),
-            Expr::Number(n) => Value::Number(*n),
-            Expr::Boolean(b) => Value::Boolean(*b),
-            Expr::Nil => Value::Nil,
+            Expr::String(s) => Ok(Value::String(Rc::clone(s))),
+            Expr::Number(n) => Ok(Value::Number(*n)),
+            Expr::Boolean(b) => Ok(Value::Boolean(*b)),
+            Expr::Nil => Ok(Value::Nil),
+            Expr::Array(elements) => {
+                let values = elements
+                    .iter()
+                    .map(|element| self.evaluate_expression(element))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Array(values))
+            }
             Expr::Identifier(name) => {
-                self.variables.get(name).cloned().unwrap_or_else(|| {
-                    eprintln!("Undefined variable: {}", name);
-                    Value::Nil
-                })
+                if let Some(value) = self.variables.get(name) {
+                    return Ok(value.clone());
+                }
+                match self.function_overloads(name).map(Vec::as_slice) {
+                    Some([func]) => Ok(Value::Function(Rc::clone(func))),
+                    Some([..]) => Err(RuntimeError::new(format!(
+                        "Function '{}' has several definitions, so it can't be used as a value",
+                        name
+                    ))),
+                    None => Err(RuntimeError::new(format!(
+                        "[{}] Undefined variable: {}",
+                        UNDEFINED_VARIABLE, name
+                    ))),
+                }
+            }
+            Expr::Binary {
+                left,
+                operator: operator @ (BinaryOp::And | BinaryOp::Or),
+                right,
+            } => {
+                // Short-circuit: the right side is skipped when the left
+                // already decides the result
+                let left_val = self.evaluate_expression(left)?;
+                let left_truthy = self.is_truthy(&left_val);
+                if left_truthy == (*operator == BinaryOp::Or) {
+                    return Ok(Value::Boolean(left_truthy));
+                }
+                let right_val = self.evaluate_expression(right)?;
+                Ok(Value::Boolean(self.is_truthy(&right_val)))
             }
             Expr::Binary {
                 left,
                 operator,
                 right,
             } => {
-                let left_val = self.evaluate_expression(left);
-                let right_val = self.evaluate_expression(right);
+                let left_val = self.evaluate_expression(left)?;
+                let right_val = self.evaluate_expression(right)?;
                 self.evaluate_binary_op(&left_val, operator, &right_val)
             }
             Expr::Unary { operator, operand } => {
-                let operand_val = self.evaluate_expression(operand);
+                let operand_val = self.evaluate_expression(operand)?;
                 self.evaluate_unary_op(operator, &operand_val)
             }
-            Expr::Call { callee, arguments } => {
-                // Evaluate callee
-                if let Expr::Identifier(name) = callee.as_ref() {
-                    // Built-in functions
-                    if name == "print" {
-                        let mut output = String::new();
-                        for arg in arguments {
-                            let value = self.evaluate_expression(arg);
-                            output.push_str(&self.value_to_string(&value));
-                        }
-                        println!("{}", output);
-                        return Value::Nil;
+            Expr::Call {
+                callee,
+                arguments,
+                named_arguments,
+            } => {
+                let Expr::Identifier(name) = callee.as_ref() else {
+                    return Err(RuntimeError::new(format!("Cannot call {}", callee)));
+                };
+                let (value, _) = self.call_function(name, None, arguments, named_arguments)?;
+                Ok(value)
+            }
+            Expr::MethodCall {
+                receiver,
+                method,
+                arguments,
+                named_arguments,
+            } => {
+                // `Point.new(...)` calls the associated function `fn Point.new`
+                if let Expr::Identifier(type_name) = receiver.as_ref()
+                    && !self.variables.contains_key(type_name)
+                    && (self.structs.contains_key(&**type_name)
+                        || self.type_aliases.contains_key(&**type_name))
+                {
+                    let qualified = format!("{}.{}", type_name, method);
+                    if self.function_overloads(&qualified).is_none() {
+                        return Err(RuntimeError::new(format!(
+                            "Type '{}' has no associated function '{}'",
+                            type_name, method
+                        )));
                     }
+                    let (value, _) =
+                        self.call_function(&qualified, None, arguments, named_arguments)?;
+                    return Ok(value);
+                }
 
-                    // User-defined functions
-                    if let Some(func) = self.functions.get(name).cloned() {
-                        // Evaluate arguments
-                        let mut arg_values = Vec::new();
-                        for arg in arguments {
-                            arg_values.push(self.evaluate_expression(arg));
-                        }
+                let target = match receiver.as_ref() {
+                    Expr::Identifier(name) if self.variables.contains_key(name) => Some(name),
+                    _ => None,
+                };
+                let receiver = self.evaluate_expression(receiver)?;
+                let receiver_type = match &receiver {
+                    Value::Nil => {
+                        return Err(RuntimeError::new(format!(
+                            "Cannot call method '{}' on nil",
+                            method
+                        )));
+                    }
+                    Value::Struct { type_name, .. } => Some(type_name.clone()),
+                    _ => None,
+                };
+                let (value, updated) =
+                    self.call_function(method, Some(receiver), arguments, named_arguments)?;
 
-                        // Check parameter count
-                        if arg_values.len() != func.params.len() {
-                            eprintln!(
-                                "Function '{}' expects {} arguments, got {}",
-                                name,
-                                func.params.len(),
-                                arg_values.len()
-                            );
-                            return Value::Nil;
-                        }
+                // Changes a method makes to a struct receiver are stored back
+                // in the variable it was called on
+                if let (Some(target), Some(receiver_type)) = (target, receiver_type)
+                    && let Some(updated) = updated
+                    && matches!(&updated, Value::Struct { type_name, .. } if *type_name == receiver_type)
+                    && let Some(slot) = self.variables.get_mut(target)
+                {
+                    *slot = updated;
+                }
+                Ok(value)
+            }
+            Expr::StructLiteral { name, fields } => self.build_struct(name, fields),
+            Expr::AnonymousStruct { .. } => self.evaluate_with_expected(expr, None),
+            Expr::FieldAccess { object, field } => {
+                let obj_value = self.evaluate_expression(object)?;
+                match obj_value {
+                    Value::Struct {
+                        type_name: _,
+                        fields,
+                    } => fields.get(&**field).cloned().ok_or_else(|| {
+                        RuntimeError::new(format!("Field '{}' not found on struct", field))
+                    }),
+                    _ => Err(RuntimeError::new("Cannot access field on non-struct value")),
+                }
+            }
+            Expr::Spread(_) => Err(RuntimeError::new(
+                "Spread '...' is only allowed in call arguments",
+            )),
+            Expr::Index { object, index } => {
+                let obj_value = self.evaluate_expression(object)?;
+                let index_value = self.evaluate_expression(index)?;
+                match (obj_value, index_value) {
+                    (Value::Array(items), Value::Number(n)) if n >= 0.0 && n.fract() == 0.0 => {
+                        let len = items.len();
+                        items.into_iter().nth(n as usize).ok_or_else(|| {
+                            RuntimeError::new(format!(
+                                "Index {} out of bounds for array of length {}",
+                                n, len
+                            ))
+                        })
+                    }
+                    (Value::Array(_), index_value) => Err(RuntimeError::new(format!(
+                        "Array index must be a non-negative integer, got {}",
+                        index_value.describe()
+                    ))),
+                    // Strings are indexed by character, never by byte
+                    (Value::String(s), Value::Number(n)) if n >= 0.0 && n.fract() == 0.0 => {
+                        let len = s.chars().count();
+                        s.chars()
+                            .nth(n as usize)
+                            .map(|ch| Value::String(ch.to_string().into()))
+                            .ok_or_else(|| {
+                                RuntimeError::new(format!(
+                                    "Index {} out of bounds for string of {} characters",
+                                    n, len
+                                ))
+                            })
+                    }
+                    (Value::String(_), index_value) => Err(RuntimeError::new(format!(
+                        "String index must be a non-negative integer, got {}",
+                        index_value.describe()
+                    ))),
+                    (Value::Map(entries), Value::String(key)) => entries
+                        .get(&*key)
+                        .cloned()
+                        .ok_or_else(|| RuntimeError::new(format!("Map has no key {:?}", key))),
+                    (Value::Map(_), index_value) => Err(RuntimeError::new(format!(
+                        "A map is indexed by a str key, got {}",
+                        index_value.describe()
+                    ))),
+                    // `p["x"]` reads a field whose name is only known at runtime
+                    (Value::Struct { type_name, fields }, Value::String(field)) => {
+                        fields.get(&*field).cloned().ok_or_else(|| {
+                            RuntimeError::new(format!(
+                                "Struct '{}' has no field '{}'",
+                                type_name, field
+                            ))
+                        })
+                    }
+                    (Value::Struct { type_name, .. }, index_value) => {
+                        Err(RuntimeError::new(format!(
+                            "Struct '{}' is indexed by field name, so the index must be a str, got {}",
+                            type_name,
+                            index_value.describe()
+                        )))
+                    }
+                    (obj_value, _) => Err(RuntimeError::new(format!(
+                        "Cannot index into {}",
+                        obj_value.describe()
+                    ))),
+                }
+            }
+        }
+    }
 
-                        // Save current variables
-                        let saved_vars = self.variables.clone();
-
-                        // Bind parameters to arguments with type checking
-                        for ((param_name, param_type), value) in
-                            func.params.iter().zip(arg_values.iter())
-                        {
-                            // Type check if type annotation exists
-                            if let Some(expected_type) = param_type {
-                                if !self.type_matches(value, expected_type) {
-                                    eprintln!(
-                                        "Type mismatch for parameter '{}' in function '{}': expected {:?}, got {:?}",
-                                        param_name, name, expected_type, value
-                                    );
-                                    return Value::Nil;
-                                }
-                            }
-                            self.variables.insert(param_name.clone(), value.clone());
-                        }
+    // `receiver` is the already evaluated left side of a method call; it
+    // goes in front of `arguments`. Returns the call's result and, for a
+    // user-defined function, the value its first parameter held at the end.
+    fn call_function(
+        &mut self,
+        name: &str,
+        receiver: Option<Value>,
+        arguments: &[Expr],
+        named_arguments: &[(Rc<str>, Expr)],
+    ) -> Result<(Value, Option<Value>), RuntimeError> {
+        // A name resolves to a variable holding a function first (e.g.
+        // `let f = add; f(1, 2)`), then a user-defined function, then a
+        // struct constructor, then a built-in, so definitions can shadow a
+        // built-in
+        let mut func = match self.variables.get(name) {
+            Some(Value::Function(value)) => Some(Rc::clone(value)),
+            _ => None,
+        };
+        let mut receiver = receiver;
+        let mut rest_arguments = arguments;
+        if func.is_none()
+            && let Some(overloads) = self.function_overloads(name).cloned()
+        {
+            // When functions for several struct types share this name, the
+            // receiver's type picks one
+            if receiver.is_none()
+                && let Some(first) = arguments.first()
+                && !matches!(first, Expr::Spread(_) | Expr::AnonymousStruct { .. })
+                && overloads.iter().any(|f| f.receiver_type().is_some())
+            {
+                receiver = Some(self.evaluate_expression(first)?);
+                rest_arguments = &arguments[1..];
+            }
+            let count = usize::from(receiver.is_some()) + rest_arguments.len();
+            func = Some(pick_overload(
+                name,
+                overloads,
+                receiver.as_ref(),
+                count + named_arguments.len(),
+                rest_arguments
+                    .iter()
+                    .any(|arg| matches!(arg, Expr::Spread(_))),
+            )?);
+        }
 
-                        // Execute function body and capture return value
-                        let mut return_value = Value::Nil;
-                        for stmt in &func.body {
-                            if let Some(ret_val) = self.execute_statement(stmt) {
-                                return_value = ret_val;
-                                break;
-                            }
-                        }
+        // `Point(1, 2)` builds a struct from its fields in declaration order
+        if func.is_none()
+            && receiver.is_none()
+            && let Some(def) = self.structs.get(name)
+        {
+            let field_names: Vec<Rc<str>> = def
+                .fields
+                .iter()
+                .map(|(f, _)| Rc::from(f.as_str()))
+                .collect();
+            if arguments.iter().any(|arg| matches!(arg, Expr::Spread(_))) {
+                return Err(RuntimeError::new(format!(
+                    "Cannot spread arguments into struct {}",
+                    name
+                )));
+            }
+            if arguments.len() + named_arguments.len() != field_names.len() {
+                return Err(RuntimeError::new(format!(
+                    "Struct '{}' has {} fields, got {} arguments",
+                    name,
+                    field_names.len(),
+                    arguments.len() + named_arguments.len()
+                )));
+            }
+            let fields: Vec<(Rc<str>, Expr)> = field_names
+                .into_iter()
+                .zip(arguments.iter().cloned())
+                .chain(named_arguments.iter().cloned())
+                .collect();
+            return Ok((self.build_struct(name, &fields)?, None));
+        }
+
+        let Some(func) = func else {
+            let value =
+                self.call_builtin_function(name, receiver, rest_arguments, named_arguments)?;
+            return Ok((value, None));
+        };
+
+        let mut arg_values = match receiver {
+            Some(value) => {
+                let rest_params = func.params.get(1..).unwrap_or(&[]);
+                let rest = self.evaluate_arguments(rest_arguments, rest_params)?;
+                let mut values = vec![value];
+                values.extend(rest);
+                values
+            }
+            None => self.evaluate_arguments(arguments, &func.params)?,
+        };
+
+        let mut named_values = Vec::new();
+        for (arg_name, arg) in named_arguments {
+            let param_type = func
+                .params
+                .iter()
+                .find(|(p, _)| p == arg_name)
+                .and_then(|(_, t)| t.as_ref());
+            named_values.push((arg_name, self.evaluate_with_expected(arg, param_type)?));
+        }
+
+        // Gather surplus positional arguments for a `...name` parameter. Named
+        // arguments may fill some of the required parameters, leaving fewer
+        // for positional ones; with no surplus the array is empty.
+        let required = func.params.len() - usize::from(func.variadic);
+        let given = arg_values.len() + named_values.len();
+        let mut packed = None;
+        if func.variadic {
+            let variadic_name = &func.params[required].0;
+            let named_required = named_values
+                .iter()
+                .filter(|(arg_name, _)| func.params[..required].iter().any(|(p, _)| p == *arg_name))
+                .count();
+            let positional = required.saturating_sub(named_required);
+            let named_variadic = named_values
+                .iter()
+                .find(|(arg_name, _)| *arg_name == variadic_name);
+            // Naming `...xs` passes the whole collection, so it must be one
+            if let Some((_, value)) = named_variadic
+                && !matches!(value, Value::Array(_))
+            {
+                return Err(RuntimeError::new(format!(
+                    "Argument '{}' of '{}' collects extra arguments, so it must be an array, got {}",
+                    variadic_name,
+                    name,
+                    value.describe()
+                )));
+            }
+            if named_variadic.is_none() && arg_values.len() >= positional {
+                packed = Some(Value::Array(arg_values.split_off(positional)));
+            }
+        }
+        let arg_count = arg_values.len() + named_values.len() + usize::from(packed.is_some());
+
+        // Check parameter count
+        if arg_count != func.params.len() {
+            let expected = if func.variadic {
+                format!("at least {}", required)
+            } else {
+                func.params.len().to_string()
+            };
+            return Err(RuntimeError::new(format!(
+                "Function '{}' expects {} arguments, got {}",
+                name, expected, given
+            )));
+        }
+
+        // Place named arguments in their parameter's position
+        let mut bound: Vec<Option<Value>> = arg_values.into_iter().map(Some).collect();
+        bound.resize(func.params.len(), None);
+        if let Some(array) = packed {
+            bound[required] = Some(array);
+        }
+        for (arg_name, value) in named_values {
+            match func.params.iter().position(|(p, _)| p == arg_name) {
+                Some(index) if bound[index].is_none() => bound[index] = Some(value),
+                Some(_) => {
+                    return Err(RuntimeError::new(format!(
+                        "Argument '{}' given more than once in call to '{}'",
+                        arg_name, name
+                    )));
+                }
+                None => {
+                    return Err(RuntimeError::new(format!(
+                        "Function '{}' has no parameter named '{}'",
+                        name, arg_name
+                    )));
+                }
+            }
+        }
+        let arg_values: Vec<Value> = bound.into_iter().flatten().collect();
 
-                        // Restore variables
-                        self.variables = saved_vars;
+        self.invoke(&func, arg_values)
+    }
 
-                        return return_value;
+    // Calls the built-in `name`, with the same argument conventions as
+    // `call_function`
+    fn call_builtin_function(
+        &mut self,
+        name: &str,
+        receiver: Option<Value>,
+        arguments: &[Expr],
+        named_arguments: &[(Rc<str>, Expr)],
+    ) -> Result<Value, RuntimeError> {
+        if name == "print" {
+            // `sep` goes between the values and `end` after them
+            let mut sep: Rc<str> = "".into();
+            let mut end: Rc<str> = "\n".into();
+            for (arg_name, arg) in named_arguments {
+                let slot = match &**arg_name {
+                    "sep" => &mut sep,
+                    "end" => &mut end,
+                    _ => {
+                        return Err(RuntimeError::new(format!(
+                            "print has no parameter named '{}'",
+                            arg_name
+                        )));
+                    }
+                };
+                match self.evaluate_expression(arg)? {
+                    Value::String(s) => *slot = s,
+                    other => {
+                        return Err(RuntimeError::new(format!(
+                            "print's '{}' must be a string, got {}",
+                            arg_name,
+                            other.describe()
+                        )));
                     }
+                }
+            }
+
+            let values = self.evaluate_arguments(arguments, &[])?;
+            let output: Vec<String> = receiver
+                .into_iter()
+                .chain(values)
+                .map(|value| self.value_to_string(&value))
+                .collect();
+            self.write_output(&format!("{}{}", output.join(&sep), end))?;
+            return Ok(Value::Nil);
+        }
+
+        // `assert` sees its argument unevaluated so a failure can quote it
+        if name == "assert" {
+            if receiver.is_some() {
+                return Err(RuntimeError::new("assert can't be called as a method"));
+            }
+            let [condition] = arguments else {
+                return Err(RuntimeError::new(format!(
+                    "assert expects 1 argument, got {}",
+                    arguments.len() + named_arguments.len()
+                )));
+            };
+            if !named_arguments.is_empty() {
+                return Err(RuntimeError::new("assert takes no named arguments"));
+            }
+            let value = self.evaluate_expression(condition)?;
+            if self.is_truthy(&value) {
+                return Ok(Value::Nil);
+            }
+            return Err(RuntimeError::new(self.assertion_message(condition)));
+        }
+
+        if builtins::lookup(name).is_some() {
+            if let Some((arg_name, _)) = named_arguments.first() {
+                return Err(RuntimeError::new(format!(
+                    "{} has no parameter named '{}'",
+                    name, arg_name
+                )));
+            }
 
-                    // Unknown function
-                    eprintln!("Undefined function: {}", name);
-                    return Value::Nil;
+            let mut arg_values: Vec<Value> = receiver.into_iter().collect();
+            arg_values.extend(self.evaluate_arguments(arguments, &[])?);
+            return self.call_builtin(name, &arg_values);
+        }
+
+        Err(RuntimeError::new(format!(
+            "[{}] Undefined function: {}",
+            UNDEFINED_FUNCTION, name
+        )))
+    }
+
+    // Run a user function on arguments already matched to its parameters
+    // Runs `func`; returns its result and the final value of its first
+    // parameter
+    fn invoke(
+        &mut self,
+        func: &Function,
+        arg_values: Vec<Value>,
+    ) -> Result<(Value, Option<Value>), RuntimeError> {
+        let name = &func.name;
+        if arg_values.len() != func.params.len() {
+            return Err(RuntimeError::new(format!(
+                "Function '{}' expects {} arguments, got {}",
+                name,
+                func.params.len(),
+                arg_values.len()
+            )));
+        }
+
+        // Type check arguments that have a type annotation; a variadic
+        // parameter's type applies to each value it collected
+        for (i, ((param_name, param_type), value)) in
+            func.params.iter().zip(arg_values.iter()).enumerate()
+        {
+            let Some(expected_type) = param_type else {
+                continue;
+            };
+            let values = match value {
+                Value::Array(items) if func.variadic && i == func.params.len() - 1 => {
+                    items.as_slice()
                 }
+                _ => std::slice::from_ref(value),
+            };
+            if let Some(value) = values
+                .iter()
+                .find(|value| !self.type_matches(value, expected_type))
+            {
+                return Err(RuntimeError::new(format!(
+                    "Type mismatch for parameter '{}' in function '{}': expected {}, got {}",
+                    param_name,
+                    name,
+                    expected_type,
+                    value.describe()
+                )));
+            }
+        }
+
+        if let Some(profile) = &mut self.profile {
+            profile.stats.entry(name.clone()).or_default().calls += 1;
+        }
 
-                // For non-identifier callees, just print for now
-                println!("Function call: {:?}", callee);
+        let memo_key = func
+            .memo
+            .then(|| (name.clone(), arg_values.iter().map(MemoKey::new).collect()));
+        if let Some(cached) = memo_key.as_ref().and_then(|key| self.memo_cache.get(key)) {
+            return Ok((cached.clone(), None));
+        }
+
+        // Bind parameters to arguments, in the call's own frame
+        self.variables.push_frame(&func.captured);
+        let last = func.params.len().saturating_sub(1);
+        for (index, ((param_name, param_type), value)) in
+            func.params.iter().zip(arg_values).enumerate()
+        {
+            // A `...name` parameter holds an array of its declared type
+            let declared = param_type
+                .clone()
+                .filter(|_| !(func.variadic && index == last));
+            match declared {
+                Some(typ) => self
+                    .variables
+                    .insert_typed(Rc::clone(param_name), value, typ),
+                None => self.variables.insert(Rc::clone(param_name), value),
+            }
+        }
+
+        // Execute function body and capture return value
+        if let Some(profile) = &mut self.profile {
+            profile.stack.push(name.clone());
+        }
+        let result = self.execute_body(&func.body).map_err(|mut err| {
+            err.stack.push(name.clone());
+            err
+        });
+        if let Some(profile) = &mut self.profile {
+            profile.stack.pop();
+        }
+
+        // Drop the frame, with any functions the body defined, even when
+        // the body failed
+        let mut call_scope = self.variables.pop_frame();
+        let first_param = func
+            .params
+            .first()
+            .and_then(|(param, _)| call_scope.remove(param));
+
+        // (the checker rejects `break`/`continue` outside a loop)
+        let mut value = match result? {
+            Some(ControlFlow::Return(value)) => value,
+            _ => Value::Nil,
+        };
+        if let Some(return_type) = &func.return_type
+            && !self.type_matches(&value, return_type)
+        {
+            // Leniently, a number or bool returned as `str` is converted
+            if self.lenient_returns
+                && *return_type == Type::Str
+                && matches!(value, Value::Number(_) | Value::Boolean(_))
+            {
+                value = Value::String(self.value_to_string(&value).into());
+            } else {
+                return Err(RuntimeError::new(format!(
+                    "Function '{}' should return {}, got {}",
+                    name,
+                    return_type,
+                    value.describe()
+                )));
+            }
+        }
+        if let Some(key) = memo_key {
+            self.memo_cache.insert(key, value.clone());
+        }
+        Ok((value, first_param))
+    }
+
+    // Built-in functions that operate on already-evaluated arguments
+    fn call_builtin(&mut self, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+        let value = match (name, args) {
+            ("approx_eq", [Value::Number(a), Value::Number(b), Value::Number(tolerance)]) => {
+                Value::Boolean((a - b).abs() <= *tolerance)
+            }
+            ("len", [Value::String(s)]) => Value::Number(s.chars().count() as f64),
+            ("len", [Value::Array(items)]) => Value::Number(items.len() as f64),
+            ("len", [Value::Struct { fields, .. }]) => Value::Number(fields.len() as f64),
+            ("len", [Value::Map(entries)]) => Value::Number(entries.len() as f64),
+            ("len", _) => {
+                return Err(RuntimeError::new(format!(
+                    "len expects a string, an array, a struct or a map, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("byte_len", [Value::String(s)]) => Value::Number(s.len() as f64),
+            ("byte_len", _) => {
+                return Err(RuntimeError::new(format!(
+                    "byte_len expects a string, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("trim_start", [Value::String(s)]) => Value::String(s.trim_start().into()),
+            ("trim_end", [Value::String(s)]) => Value::String(s.trim_end().into()),
+            ("trim_start" | "trim_end", _) => {
+                return Err(RuntimeError::new(format!(
+                    "{} expects a string, got {}",
+                    name,
+                    describe_args(args)
+                )));
+            }
+            ("replace", [Value::String(s), Value::String(old), Value::String(new)]) => {
+                Value::String(s.replace(&**old, new).into())
+            }
+            ("replace", _) => {
+                return Err(RuntimeError::new(format!(
+                    "replace expects three strings, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("range", _) => {
+                let (start, step, count) = self.range_steps(args)?;
+                // `for` walks a range lazily, but an array needs all of it in memory
+                if count > MAX_RANGE_LEN {
+                    return Err(RuntimeError::new(format!(
+                        "range of more than {} elements is too large to build",
+                        MAX_RANGE_LEN
+                    )));
+                }
+                Value::Array(
+                    (0..count)
+                        .map(|i| Value::Number(start + i as f64 * step))
+                        .collect(),
+                )
+            }
+            ("approx_eq", _) => {
+                return Err(RuntimeError::new(format!(
+                    "approx_eq expects three numbers, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("floor_div", [Value::Number(_), Value::Number(b)]) if *b == 0.0 => {
+                return Err(RuntimeError::new("Division by zero in floor_div"));
+            }
+            ("floor_div", [Value::Number(a), Value::Number(b)]) => Value::Number((a / b).floor()),
+            ("floor_div", _) => {
+                return Err(RuntimeError::new(format!(
+                    "floor_div expects two numbers, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("to_map", [Value::Struct { fields, .. }]) => Value::Map(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+            ),
+            ("to_map", _) => {
+                return Err(RuntimeError::new(format!(
+                    "to_map expects a struct, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("from_map", [Value::String(type_name), Value::Map(entries)]) => {
+                self.struct_from_map(type_name, entries)?
+            }
+            ("from_map", _) => {
+                return Err(RuntimeError::new(format!(
+                    "from_map expects a struct name and a map, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("int", [Value::Number(n)]) => Value::Number(n.trunc()),
+            ("int", _) => {
+                return Err(RuntimeError::new(format!(
+                    "int expects a number, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("sort_by", [Value::Array(items), Value::Function(compare)]) => {
+                Value::Array(self.merge_sort(items.clone(), compare)?)
+            }
+            ("sort_by", _) => {
+                return Err(RuntimeError::new(format!(
+                    "sort_by expects an array and a comparison function, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("debug", [value]) => {
+                let text = format!("{}\n", self.value_to_pretty_string(value, 0));
+                self.write_output(&text)?;
                 Value::Nil
             }
-            Expr::Grouping(inner) => self.evaluate_expression(inner),
-            Expr::StructLiteral { name, fields } => {
-                // Get struct definition
-                let struct_def = self.structs.get(name).cloned().unwrap_or_else(|| {
-                    eprintln!("Undefined struct: {}", name);
-                    std::process::exit(1);
-                });
-
-                // Create a HashMap for field values
-                let mut field_values = HashMap::new();
-
-                // Check that all defined fields are provided and type-check them
-                for (field_name, field_type) in &struct_def.fields {
-                    // Find the field in the provided fields
-                    let field_value = fields
-                        .iter()
-                        .find(|(name, _)| name == field_name)
-                        .map(|(_, expr)| self.evaluate_expression(expr));
-
-                    match field_value {
-                        Some(value) => {
-                            // Type check
-                            if !self.type_matches(&value, field_type) {
-                                eprintln!(
-                                    "Type mismatch for field '{}': expected {:?}, got {:?}",
-                                    field_name, field_type, value
-                                );
-                                std::process::exit(1);
-                            }
-                            field_values.insert(field_name.clone(), value);
-                        }
-                        None => {
-                            eprintln!("Missing field '{}' in struct {}", field_name, name);
-                            std::process::exit(1);
-                        }
-                    }
+            ("json_stringify", [value]) => match self.value_to_json(value) {
+                Ok(json) => Value::String(json.into()),
+                Err(message) => {
+                    return Err(RuntimeError::new(format!("json_stringify: {}", message)));
                 }
+            },
+            ("random", []) => Value::Number(self.rng.next_f64()),
+            ("random", _) => {
+                return Err(RuntimeError::new(format!(
+                    "random takes no arguments, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("random_int", [Value::Number(lo), Value::Number(hi)])
+                if lo.fract() == 0.0 && hi.fract() == 0.0 && lo <= hi =>
+            {
+                // The cast saturates, so a span beyond u64 shows up as overflow
+                let Some(span) = ((hi - lo) as u64).checked_add(1) else {
+                    return Err(RuntimeError::new(format!(
+                        "random_int range from {} to {} is too large",
+                        lo, hi
+                    )));
+                };
+                Value::Number(lo + (self.rng.next_u64() % span) as f64)
+            }
+            ("random_int", _) => {
+                return Err(RuntimeError::new(format!(
+                    "random_int expects two integers with lo <= hi, got {}",
+                    describe_args(args)
+                )));
+            }
+            ("seed", [Value::Number(n)]) => {
+                self.rng = Rng::new(*n as u64);
+                Value::Nil
+            }
+            ("bool", [value]) => Value::Boolean(self.is_truthy(value)),
+            ("bool", _) => {
+                return Err(RuntimeError::new(format!(
+                    "bool expects 1 argument, got {}",
+                    args.len()
+                )));
+            }
+            ("seed", _) => {
+                return Err(RuntimeError::new(format!(
+                    "seed expects a number, got {}",
+                    describe_args(args)
+                )));
+            }
+            _ => {
+                return Err(RuntimeError::new(format!(
+                    "[{}] Undefined function: {}",
+                    UNDEFINED_FUNCTION, name
+                )));
+            }
+        };
+        Ok(value)
+    }
 
-                // Check for extra fields
-                for (provided_field, _) in fields {
-                    if !struct_def
-                        .fields
-                        .iter()
-                        .any(|(name, _)| name == provided_field)
-                    {
-                        eprintln!(
-                            "Unknown field '{}' in struct {}",
-                            provided_field, name
-                        );
-                        std::process::exit(1);
-                    }
-                }
+    // Stable sort for `sort_by`. `compare(a, b)` returns a number (negative
+    // when a goes first) or a bool (true when a goes first). Sorting by hand
+    // lets a failing comparison stop the sort with its error.
+    fn merge_sort(
+        &mut self,
+        mut items: Vec<Value>,
+        compare: &Function,
+    ) -> Result<Vec<Value>, RuntimeError> {
+        if items.len() <= 1 {
+            return Ok(items);
+        }
+        let right = items.split_off(items.len() / 2);
+        let mut left = self.merge_sort(items, compare)?.into_iter().peekable();
+        let mut right = self.merge_sort(right, compare)?.into_iter().peekable();
 
-                Value::Struct {
-                    type_name: name.clone(),
-                    fields: field_values,
+        let mut merged = Vec::with_capacity(left.len() + right.len());
+        while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+            // Only take from the right when it strictly goes first, keeping
+            // equal elements in their original order
+            let (ordering, _) = self.invoke(compare, vec![r.clone(), l.clone()])?;
+            let right_first = match ordering {
+                Value::Number(n) => n < 0.0,
+                Value::Boolean(b) => b,
+                other => {
+                    return Err(RuntimeError::new(format!(
+                        "sort_by comparison must return a number or a bool, got {}",
+                        other.describe()
+                    )));
                 }
+            };
+            let next = if right_first {
+                right.next()
+            } else {
+                left.next()
+            };
+            merged.extend(next);
+        }
+        merged.extend(left);
+        merged.extend(right);
+        Ok(merged)
+    }
+
+    // "assertion failed: x > 5 (x was 3)", listing each variable the
+    // condition reads
+    fn assertion_message(&self, condition: &Expr) -> String {
+        let mut names = Vec::new();
+        collect_identifiers(condition, &mut names);
+
+        let values: Vec<String> = names
+            .iter()
+            .filter_map(|name| {
+                let value = match self.variables.get(name)? {
+                    Value::String(s) => format!("{:?}", s),
+                    value => self.value_to_string(value),
+                };
+                Some(format!("{} was {}", name, value))
+            })
+            .collect();
+
+        if values.is_empty() {
+            format!("assertion failed: {}", condition)
+        } else {
+            format!("assertion failed: {} ({})", condition, values.join(", "))
+        }
+    }
+
+    // Shared by the `range` built-in and the lazy `for ... in range(...)` loop.
+    // Returns the start, the step and how many values there are; each value is
+    // computed from its index so steps don't accumulate rounding error.
+    fn range_steps(&self, args: &[Value]) -> Result<(f64, f64, u64), RuntimeError> {
+        let (start, end, step) = match args {
+            [Value::Number(start), Value::Number(end)] => (*start, *end, 1.0),
+            [
+                Value::Number(start),
+                Value::Number(end),
+                Value::Number(step),
+            ] => (*start, *end, *step),
+            _ => {
+                return Err(RuntimeError::new(format!(
+                    "range expects two or three numbers, got {}",
+                    describe_args(args)
+                )));
             }
-            Expr::FieldAccess { object, field } => {
-                let obj_value = self.evaluate_expression(object);
-                match obj_value {
-                    Value::Struct {
-                        type_name: _,
-                        fields,
-                    } => fields.get(field).cloned().unwrap_or_else(|| {
-                        eprintln!("Field '{}' not found on struct", field);
-                        std::process::exit(1);
-                    }),
-                    _ => {
-                        eprintln!("Cannot access field on non-struct value");
-                        std::process::exit(1);
+        };
+        if step == 0.0 || !step.is_finite() {
+            return Err(RuntimeError::new(format!(
+                "range step must be a non-zero number, got {}",
+                step
+            )));
+        }
+
+        // Empty when the step points away from the end
+        let count = ((end - start) / step).ceil();
+        Ok((start, step, if count > 0.0 { count as u64 } else { 0 }))
+    }
+
+    fn build_struct(
+        &mut self,
+        name: &str,
+        fields: &[(Rc<str>, Expr)],
+    ) -> Result<Value, RuntimeError> {
+        // Get struct definition
+        let struct_def = self
+            .structs
+            .get(name)
+            .cloned()
+            .ok_or_else(|| RuntimeError::new(format!("Undefined struct: {}", name)))?;
+
+        let names: Vec<&str> = fields.iter().map(|(field, _)| &**field).collect();
+        check_field_names(name, &struct_def, &names)?;
+
+        // Evaluate in the order the literal is written, then type check in
+        // declaration order
+        let mut field_values = HashMap::with_capacity(fields.len());
+        for (field_name, expr) in fields {
+            let value = self.evaluate_expression(expr)?;
+            field_values.insert(field_name.to_string(), value);
+        }
+        self.typed_struct(name, &struct_def, field_values)
+    }
+
+    // `from_map("Point", m)`: the map's keys must be exactly the fields
+    fn struct_from_map(
+        &self,
+        name: &str,
+        entries: &BTreeMap<String, Value>,
+    ) -> Result<Value, RuntimeError> {
+        let struct_def = self
+            .structs
+            .get(name)
+            .ok_or_else(|| RuntimeError::new(format!("Undefined struct: {}", name)))?;
+        let names: Vec<&str> = entries.keys().map(String::as_str).collect();
+        check_field_names(name, struct_def, &names)?;
+        let field_values = entries
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        self.typed_struct(name, struct_def, field_values)
+    }
+
+    // A struct from values for exactly its fields, once each is checked
+    // against its declared type
+    fn typed_struct(
+        &self,
+        name: &str,
+        struct_def: &StructDef,
+        field_values: HashMap<String, Value>,
+    ) -> Result<Value, RuntimeError> {
+        for (field_name, field_type) in &struct_def.fields {
+            let value = &field_values[field_name];
+            if !self.type_matches(value, field_type) {
+                return Err(RuntimeError::new(format!(
+                    "Type mismatch for field '{}': expected {}, got {}",
+                    field_name,
+                    field_type,
+                    value.describe()
+                )));
+            }
+        }
+
+        Ok(Value::Struct {
+            type_name: name.to_string(),
+            fields: field_values,
+        })
+    }
+
+    // Set the field at the end of `path` inside `object`, checking the new
+    // value against the field's declared type
+    fn assign_field(
+        &self,
+        object: &mut Value,
+        path: &[Rc<str>],
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        let (field, rest) = path
+            .split_first()
+            .expect("the parser gives at least one field");
+        let Value::Struct { type_name, fields } = object else {
+            return Err(RuntimeError::new(format!(
+                "Cannot assign field '{}' on {}",
+                field,
+                object.describe()
+            )));
+        };
+        let Some(slot) = fields.get_mut(&**field) else {
+            return Err(RuntimeError::new(format!(
+                "Struct {} has no field '{}'",
+                type_name, field
+            )));
+        };
+        if !rest.is_empty() {
+            return self.assign_field(slot, rest, value);
+        }
+
+        let field_type = self
+            .structs
+            .get(type_name.as_str())
+            .and_then(|def| {
+                def.fields
+                    .iter()
+                    .find(|(name, _)| name.as_str() == &**field)
+            })
+            .map(|(_, typ)| typ);
+        if let Some(field_type) = field_type
+            && !self.type_matches(&value, field_type)
+        {
+            return Err(RuntimeError::new(format!(
+                "Type mismatch for field '{}': expected {}, got {}",
+                field,
+                field_type,
+                value.describe()
+            )));
+        }
+        *slot = value;
+        Ok(())
+    }
+
+    // Evaluate positional arguments, expanding `...array` in place. Each
+    // argument is evaluated against its parameter's type, so unnamed struct
+    // literals can be passed to typed parameters.
+    fn evaluate_arguments(
+        &mut self,
+        arguments: &[Expr],
+        params: &[(Rc<str>, Option<Type>)],
+    ) -> Result<Vec<Value>, RuntimeError> {
+        let mut values = Vec::new();
+        for arg in arguments {
+            if let Expr::Spread(inner) = arg {
+                match self.evaluate_expression(inner)? {
+                    Value::Array(items) => values.extend(items),
+                    other => {
+                        return Err(RuntimeError::new(format!(
+                            "Cannot spread non-array value {}",
+                            other.describe()
+                        )));
                     }
                 }
+                continue;
             }
+
+            let param_type = params.get(values.len()).and_then(|(_, t)| t.as_ref());
+            values.push(self.evaluate_with_expected(arg, param_type)?);
+        }
+        Ok(values)
+    }
+
+    // Evaluate an expression whose type is known from context, which lets an
+    // unnamed struct literal `{ x: 1 }` take on the expected struct type
+    fn evaluate_with_expected(
+        &mut self,
+        expr: &Expr,
+        expected_type: Option<&Type>,
+    ) -> Result<Value, RuntimeError> {
+        let Expr::AnonymousStruct { fields } = expr else {
+            return self.evaluate_expression(expr);
+        };
+
+        let struct_name = match expected_type {
+            Some(Type::Custom(name)) => Some(name),
+            Some(Type::Optional(inner)) => match inner.as_ref() {
+                Type::Custom(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match struct_name {
+            Some(name) if self.structs.contains_key(name) => self.build_struct(name, fields),
+            _ => Err(RuntimeError::new(
+                "Cannot infer the struct type of an unnamed struct literal; name the struct or annotate the expected type",
+            )),
         }
     }
 
@@ -338,7 +1840,7 @@ impl Executor {
                 // Check if it's a type alias (union type)
                 if let Some(type_alias) = self.type_aliases.get(type_name) {
                     // For string literal unions, check if value is in variants
-                    type_alias.variants.contains(s)
+                    type_alias.variants.iter().any(|v| **v == **s)
                 } else {
                     // Not a type alias, might be trying to use a struct type for a string
                     false
@@ -356,17 +1858,78 @@ impl Executor {
             }
             (Value::String(s), Type::Union(variants)) => {
                 // Direct union type check
-                variants.contains(s)
+                variants.iter().any(|v| **v == **s)
             }
+            (Value::Nil, Type::Optional(_)) => true,
+            (value, Type::Optional(inner)) => self.type_matches(value, inner),
             _ => false,
         }
     }
 
-    fn evaluate_binary_op(&self, left: &Value, op: &BinaryOp, right: &Value) -> Value {
-        match (left, op, right) {
+    // Structural equality, leaving out struct fields marked `@skip_eq`
+    fn values_equal(&self, left: &Value, right: &Value) -> bool {
+        match (left, right) {
+            (Value::Array(l), Value::Array(r)) => {
+                l.len() == r.len() && l.iter().zip(r).all(|(a, b)| self.values_equal(a, b))
+            }
+            (
+                Value::Struct {
+                    type_name: l_type,
+                    fields: l_fields,
+                },
+                Value::Struct {
+                    type_name: r_type,
+                    fields: r_fields,
+                },
+            ) => {
+                let skip_eq = self.structs.get(l_type).map(|def| &def.skip_eq);
+                l_type == r_type
+                    && l_fields.len() == r_fields.len()
+                    && l_fields.iter().all(|(name, value)| {
+                        skip_eq.is_some_and(|skip| skip.contains(name))
+                            || r_fields
+                                .get(name)
+                                .is_some_and(|other| self.values_equal(value, other))
+                    })
+            }
+            (Value::Map(l), Value::Map(r)) => {
+                l.len() == r.len()
+                    && l.iter().zip(r).all(|((l_key, l_value), (r_key, r_value))| {
+                        l_key == r_key && self.values_equal(l_value, r_value)
+                    })
+            }
+            _ => left == right,
+        }
+    }
+
+    pub(crate) fn evaluate_binary_op(
+        &self,
+        left: &Value,
+        op: &BinaryOp,
+        right: &Value,
+    ) -> Result<Value, RuntimeError> {
+        let value = match (left, op, right) {
+            // Array concatenation; checked before strings so `[1] + "x"` is
+            // an error rather than the text "[1]x"
+            (Value::Array(l), BinaryOp::Add, Value::Array(r)) => {
+                Value::Array(l.iter().chain(r).cloned().collect())
+            }
+            (Value::Array(_), BinaryOp::Add, other) | (other, BinaryOp::Add, Value::Array(_)) => {
+                return Err(RuntimeError::new(format!(
+                    "Cannot add an array and {}; both sides of '+' must be arrays",
+                    other.describe()
+                )));
+            }
             // String concatenation
             (Value::String(l), BinaryOp::Add, Value::String(r)) => {
-                Value::String(format!("{}{}", l, r))
+                Value::String(format!("{}{}", l, r).into())
+            }
+            // Adding anything else to a string stringifies it, as `print` would
+            (Value::String(l), BinaryOp::Add, r) => {
+                Value::String(format!("{}{}", l, self.value_to_string(r)).into())
+            }
+            (l, BinaryOp::Add, Value::String(r)) => {
+                Value::String(format!("{}{}", self.value_to_string(l), r).into())
             }
             // Number operations
             (Value::Number(l), BinaryOp::Add, Value::Number(r)) => Value::Number(l + r),
@@ -377,28 +1940,47 @@ impl Executor {
             (Value::Number(l), BinaryOp::LessEqual, Value::Number(r)) => Value::Boolean(l <= r),
             (Value::Number(l), BinaryOp::Greater, Value::Number(r)) => Value::Boolean(l > r),
             (Value::Number(l), BinaryOp::GreaterEqual, Value::Number(r)) => Value::Boolean(l >= r),
+            // Strings order lexicographically by Unicode code point, so "Zoo" < "apple"
+            (Value::String(l), BinaryOp::Less, Value::String(r)) => Value::Boolean(l < r),
+            (Value::String(l), BinaryOp::LessEqual, Value::String(r)) => Value::Boolean(l <= r),
+            (Value::String(l), BinaryOp::Greater, Value::String(r)) => Value::Boolean(l > r),
+            (Value::String(l), BinaryOp::GreaterEqual, Value::String(r)) => Value::Boolean(l >= r),
             // Equality (works for all types)
-            (l, BinaryOp::Equal, r) => Value::Boolean(l == r),
-            (l, BinaryOp::NotEqual, r) => Value::Boolean(l != r),
+            (l, BinaryOp::Equal, r) => Value::Boolean(self.values_equal(l, r)),
+            (l, BinaryOp::NotEqual, r) => Value::Boolean(!self.values_equal(l, r)),
+            // Both sides are already evaluated here, so this doesn't short-circuit
+            (l, BinaryOp::And, r) => Value::Boolean(self.is_truthy(l) && self.is_truthy(r)),
+            (l, BinaryOp::Or, r) => Value::Boolean(self.is_truthy(l) || self.is_truthy(r)),
             _ => {
-                println!("Invalid binary operation: {:?} {:?} {:?}", left, op, right);
-                Value::Nil
+                return Err(RuntimeError::new(format!(
+                    "Invalid binary operation: {} {} {}",
+                    left.describe(),
+                    op,
+                    right.describe()
+                )));
             }
-        }
+        };
+        Ok(value)
     }
 
-    fn evaluate_unary_op(&self, op: &UnaryOp, operand: &Value) -> Value {
+    pub(crate) fn evaluate_unary_op(
+        &self,
+        op: &UnaryOp,
+        operand: &Value,
+    ) -> Result<Value, RuntimeError> {
         match (op, operand) {
-            (UnaryOp::Negate, Value::Number(n)) => Value::Number(-n),
-            (UnaryOp::Not, val) => Value::Boolean(!self.is_truthy(val)),
-            _ => {
-                println!("Invalid unary operation: {:?} {:?}", op, operand);
-                Value::Nil
-            }
+            (UnaryOp::Negate, Value::Number(n)) => Ok(Value::Number(-n)),
+            (UnaryOp::Plus, Value::Number(n)) => Ok(Value::Number(*n)),
+            (UnaryOp::Not, val) => Ok(Value::Boolean(!self.is_truthy(val))),
+            (UnaryOp::Negate | UnaryOp::Plus, _) => Err(RuntimeError::new(format!(
+                "Cannot apply unary '{}' to {}; it only works on numbers",
+                op,
+                operand.describe()
+            ))),
         }
     }
 
-    fn is_truthy(&self, value: &Value) -> bool {
+    pub(crate) fn is_truthy(&self, value: &Value) -> bool {
         match value {
             Value::Boolean(b) => *b,
             Value::Nil => false,
@@ -406,12 +1988,17 @@ impl Executor {
         }
     }
 
-    fn value_to_string(&self, value: &Value) -> String {
+    pub fn value_to_string(&self, value: &Value) -> String {
         match value {
-            Value::String(s) => s.clone(),
+            Value::String(s) => s.to_string(),
             Value::Number(n) => n.to_string(),
             Value::Boolean(b) => b.to_string(),
             Value::Nil => "nil".to_string(),
+            Value::Array(items) => {
+                let item_strs: Vec<String> =
+                    items.iter().map(|v| self.value_to_string(v)).collect();
+                format!("[{}]", item_strs.join(", "))
+            }
             Value::Struct {
                 type_name: _,
                 fields,
@@ -422,6 +2009,504 @@ impl Executor {
                     .collect();
                 format!("{{ {} }}", field_strs.join(", "))
             }
+            Value::Function(func) => {
+                let mut params: Vec<String> = func
+                    .params
+                    .iter()
+                    .map(|(name, param_type)| match param_type {
+                        Some(param_type) => format!("{}: {}", name, param_type),
+                        None => name.to_string(),
+                    })
+                    .collect();
+                if func.variadic
+                    && let Some(last) = params.last_mut()
+                {
+                    last.insert_str(0, "...");
+                }
+                match &func.return_type {
+                    Some(return_type) => format!("fn({}) -> {}", params.join(", "), return_type),
+                    None => format!("fn({})", params.join(", ")),
+                }
+            }
+            // Keys are quoted to tell a map apart from a struct
+            Value::Map(entries) => {
+                let entry_strs: Vec<String> = entries
+                    .iter()
+                    .map(|(k, v)| format!("{:?}: {}", k, self.value_to_string(v)))
+                    .collect();
+                format!("{{{}}}", entry_strs.join(", "))
+            }
         }
     }
+
+    // Multi-line form of `value_to_string` for `debug`; strings are quoted so
+    // they can be told apart from other values
+    fn value_to_pretty_string(&self, value: &Value, indent: usize) -> String {
+        let pad = "    ".repeat(indent + 1);
+        let close = "    ".repeat(indent);
+        match value {
+            Value::String(s) => format!("{:?}", s),
+            Value::Array(items) if items.is_empty() => "[]".to_string(),
+            Value::Array(items) => {
+                let mut out = String::from("[\n");
+                for item in items {
+                    let item = self.value_to_pretty_string(item, indent + 1);
+                    out.push_str(&format!("{}{},\n", pad, item));
+                }
+                out.push_str(&format!("{}]", close));
+                out
+            }
+            Value::Struct { type_name, fields } if fields.is_empty() => {
+                format!("{} {{}}", type_name)
+            }
+            Value::Struct { type_name, fields } => {
+                let mut out = format!("{} {{\n", type_name);
+                for name in self.field_order(type_name, fields) {
+                    let field = self.value_to_pretty_string(&fields[name], indent + 1);
+                    out.push_str(&format!("{}{}: {},\n", pad, name, field));
+                }
+                out.push_str(&format!("{}}}", close));
+                out
+            }
+            other => self.value_to_string(other),
+        }
+    }
+
+    // Field names in the order the struct declares them
+    fn field_order<'v>(
+        &'v self,
+        type_name: &str,
+        fields: &'v HashMap<String, Value>,
+    ) -> Vec<&'v String> {
+        match self.structs.get(type_name) {
+            Some(def) => def
+                .fields
+                .iter()
+                .map(|(name, _)| name)
+                .filter(|name| fields.contains_key(*name))
+                .collect(),
+            None => fields.keys().collect(),
+        }
+    }
+
+    // Struct fields are written in the order the struct declares them
+    fn value_to_json(&self, value: &Value) -> Result<String, String> {
+        match value {
+            Value::String(s) => Ok(json_quote(s)),
+            Value::Number(n) if n.is_finite() => Ok(n.to_string()),
+            Value::Number(n) => Err(format!("{} can't be represented in JSON", n)),
+            Value::Boolean(b) => Ok(b.to_string()),
+            Value::Nil => Ok("null".to_string()),
+            Value::Array(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.value_to_json(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(format!("[{}]", items.join(",")))
+            }
+            Value::Struct { type_name, fields } => {
+                let mut members = Vec::new();
+                for name in self.field_order(type_name, fields) {
+                    let field_json = self.value_to_json(&fields[name])?;
+                    members.push(format!("{}:{}", json_quote(name), field_json));
+                }
+                Ok(format!("{{{}}}", members.join(",")))
+            }
+            Value::Function(_) => Err("a function can't be represented in JSON".to_string()),
+            Value::Map(entries) => {
+                let mut members = Vec::new();
+                for (key, value) in entries {
+                    members.push(format!(
+                        "{}:{}",
+                        json_quote(key),
+                        self.value_to_json(value)?
+                    ));
+                }
+                Ok(format!("{{{}}}", members.join(",")))
+            }
+        }
+    }
+}
+
+// The field names given for a struct must each appear once and cover
+// exactly its fields. Missing fields are reported in declaration order, so
+// the first one is always the one named.
+fn check_field_names(
+    name: &str,
+    struct_def: &StructDef,
+    provided: &[&str],
+) -> Result<(), RuntimeError> {
+    let mut seen: HashSet<&str> = HashSet::with_capacity(provided.len());
+    for field_name in provided {
+        if !seen.insert(field_name) {
+            return Err(RuntimeError::new(format!(
+                "Field '{}' given more than once in struct {}",
+                field_name, name
+            )));
+        }
+    }
+
+    if let Some((missing, _)) = struct_def
+        .fields
+        .iter()
+        .find(|(field_name, _)| !seen.contains(field_name.as_str()))
+    {
+        return Err(RuntimeError::new(format!(
+            "Missing field '{}' in struct {}",
+            missing, name
+        )));
+    }
+    if let Some(extra) = provided
+        .iter()
+        .find(|field_name| !struct_def.fields.iter().any(|(f, _)| f == *field_name))
+    {
+        return Err(RuntimeError::new(format!(
+            "Unknown field '{}' in struct {}",
+            extra, name
+        )));
+    }
+    Ok(())
+}
+
+// Of the definitions sharing a name, the last one that takes `count`
+// arguments, preferring one whose first parameter has the receiver's type.
+// A spread argument's length isn't known yet, so then any count fits.
+fn pick_overload(
+    name: &str,
+    overloads: Vec<Rc<Function>>,
+    receiver: Option<&Value>,
+    count: usize,
+    spread: bool,
+) -> Result<Rc<Function>, RuntimeError> {
+    // A lone definition reports its own argument count mismatch
+    if let [only] = overloads.as_slice() {
+        return Ok(Rc::clone(only));
+    }
+    let receiver_type = match receiver {
+        Some(Value::Struct { type_name, .. }) => Some(type_name.as_str()),
+        _ => None,
+    };
+    let fitting: Vec<&Rc<Function>> = overloads
+        .iter()
+        .filter(|func| spread || func.accepts(count))
+        .collect();
+    let chosen = fitting
+        .iter()
+        .rev()
+        .find(|func| receiver_type.is_some() && func.receiver_type() == receiver_type)
+        .or(fitting.last());
+    if let Some(func) = chosen {
+        return Ok(Rc::clone(func));
+    }
+
+    let mut expected: Vec<String> = overloads
+        .iter()
+        .map(|func| {
+            if func.variadic {
+                format!("at least {}", func.params.len() - 1)
+            } else {
+                func.params.len().to_string()
+            }
+        })
+        .collect();
+    expected.sort();
+    expected.dedup();
+    Err(RuntimeError::new(format!(
+        "Function '{}' expects {} arguments, got {}",
+        name,
+        expected.join(" or "),
+        count
+    )))
+}
+
+// Also raised by the bytecode VM, so both report it the same way
+pub(crate) fn undeclared_assignment(name: &str) -> RuntimeError {
+    RuntimeError::new(format!(
+        "[{}] Cannot assign to '{}' before it is declared; use 'let {} = ...' first",
+        UNDEFINED_VARIABLE, name, name
+    ))
+}
+
+// Variable names read by an expression, first occurrence first
+fn collect_identifiers(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::Identifier(name) => {
+            if !names.iter().any(|seen| **seen == **name) {
+                names.push(name.to_string());
+            }
+        }
+        Expr::Array(elements) => {
+            for element in elements {
+                collect_identifiers(element, names);
+            }
+        }
+        Expr::Binary { left, right, .. } => {
+            collect_identifiers(left, names);
+            collect_identifiers(right, names);
+        }
+        Expr::Unary { operand, .. } => collect_identifiers(operand, names),
+        // The callee is a function name, not a variable
+        Expr::MethodCall {
+            receiver,
+            arguments,
+            named_arguments,
+            ..
+        } => {
+            collect_identifiers(receiver, names);
+            for arg in arguments {
+                collect_identifiers(arg, names);
+            }
+            for (_, arg) in named_arguments {
+                collect_identifiers(arg, names);
+            }
+        }
+        Expr::Call {
+            arguments,
+            named_arguments,
+            ..
+        } => {
+            for arg in arguments {
+                collect_identifiers(arg, names);
+            }
+            for (_, arg) in named_arguments {
+                collect_identifiers(arg, names);
+            }
+        }
+        Expr::StructLiteral { fields, .. } | Expr::AnonymousStruct { fields } => {
+            for (_, value) in fields {
+                collect_identifiers(value, names);
+            }
+        }
+        Expr::FieldAccess { object, .. } | Expr::Spread(object) => {
+            collect_identifiers(object, names)
+        }
+        Expr::Index { object, index } => {
+            collect_identifiers(object, names);
+            collect_identifiers(index, names);
+        }
+        Expr::String(_) | Expr::Number(_) | Expr::Boolean(_) | Expr::Nil => {}
+    }
+}
+
+pub fn json_quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Lexer, Parser};
+
+    fn run(source: &str) -> Result<Executor, RuntimeError> {
+        let tokens = Lexer::new(source.as_bytes().to_vec()).tokenize();
+        let ast = Parser::new(tokens).parse().expect("program parses");
+        let mut executor = Executor::new(ast);
+        executor.exec()?;
+        Ok(executor)
+    }
+
+    fn variable(executor: &Executor, name: &str) -> Value {
+        executor
+            .variables
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| panic!("'{}' is not defined", name))
+    }
+
+    #[test]
+    fn function_defined_in_a_loop_captures_its_iteration() {
+        let executor = run(concat!(
+            "let fs = []\n",
+            "for i in range(0, 3) {\n",
+            "    fn get() { return i }\n",
+            "    fs = fs + [get]\n",
+            "}\n",
+            "let seen = []\n",
+            "for f in fs {\n",
+            "    seen = seen + [f()]\n",
+            "}",
+        ))
+        .expect("program runs");
+        assert_eq!(
+            variable(&executor, "seen"),
+            Value::Array(vec![
+                Value::Number(0.0),
+                Value::Number(1.0),
+                Value::Number(2.0)
+            ])
+        );
+    }
+
+    #[test]
+    fn nested_function_is_not_callable_from_the_top_level() {
+        let err = run("fn outer() {\n    fn inner() { return 1 }\n    return inner()\n}\nlet x = outer()\ninner()")
+            .err()
+            .expect("calling inner fails");
+        assert!(
+            err.message.contains("Undefined function: inner"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn nested_function_reads_the_enclosing_call() {
+        let executor = run("fn scale(k) {\n    fn times(x) { return x * k }\n    return times(10)\n}\nlet y = scale(3)")
+            .expect("program runs");
+        assert_eq!(variable(&executor, "y"), Value::Number(30.0));
+    }
+
+    #[test]
+    fn function_does_not_see_its_callers_variables() {
+        let err = run("fn leak() { return secret }\nfn caller() {\n    let secret = 5\n    return leak()\n}\ncaller()")
+            .err()
+            .expect("reading the caller's variable fails");
+        assert!(
+            err.message.contains("Undefined variable: secret"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn assignment_in_a_function_leaves_the_global_alone() {
+        let executor =
+            run("let g = 0\nfn bump() {\n    g = g + 1\n    return g\n}\nlet seen = bump()")
+                .expect("program runs");
+        assert_eq!(variable(&executor, "seen"), Value::Number(1.0));
+        assert_eq!(variable(&executor, "g"), Value::Number(0.0));
+    }
+
+    fn error(source: &str) -> String {
+        match run(source) {
+            Ok(_) => panic!("expected a runtime error"),
+            Err(err) => err.message,
+        }
+    }
+
+    #[test]
+    fn errors_name_values_by_their_weave_type() {
+        assert_eq!(
+            error("len(3)"),
+            "len expects a string, an array, a struct or a map, got number 3"
+        );
+        assert_eq!(
+            error("let x = 3 / nil"),
+            "Invalid binary operation: number 3 / nil"
+        );
+        assert_eq!(
+            error("for x in \"ab\" {}"),
+            "Cannot iterate over non-array value: str \"ab\""
+        );
+        assert_eq!(
+            error("let x = [1][true]"),
+            "Array index must be a non-negative integer, got bool true"
+        );
+    }
+
+    #[test]
+    fn array_plus_string_is_an_error_either_way_round() {
+        let expected = "Cannot add an array and str \"x\"; both sides of '+' must be arrays";
+        assert_eq!(error("let s = [1, 2] + \"x\""), expected);
+        assert_eq!(error("let s = \"x\" + [1, 2]"), expected);
+    }
+
+    #[test]
+    fn named_variadic_argument_must_be_an_array() {
+        let source = "fn f(a, ...xs) { return xs }\nlet r = f(xs: 1, a: 2)";
+        assert_eq!(
+            error(source),
+            "Argument 'xs' of 'f' collects extra arguments, so it must be an array, got number 1"
+        );
+        let executor =
+            run("fn f(a, ...xs) { return xs }\nlet r = f(xs: [1], a: 2)").expect("program runs");
+        assert_eq!(
+            variable(&executor, "r"),
+            Value::Array(vec![Value::Number(1.0)])
+        );
+    }
+
+    #[test]
+    fn evaluating_a_literal_again_shares_its_string() {
+        let executor = run(concat!(
+            "let seen = []\n",
+            "for i in range(0, 3) {\n",
+            "    seen = seen + [\"hello\"]\n",
+            "}",
+        ))
+        .expect("program runs");
+        let Value::Array(seen) = variable(&executor, "seen") else {
+            panic!("expected an array");
+        };
+        let strings: Vec<Rc<str>> = seen
+            .into_iter()
+            .map(|value| match value {
+                Value::String(s) => s,
+                other => panic!("expected a string, got {}", other.describe()),
+            })
+            .collect();
+        assert_eq!(strings.len(), 3);
+        assert!(strings.iter().all(|s| Rc::ptr_eq(s, &strings[0])));
+    }
+
+    #[test]
+    fn memo_keeps_arguments_of_different_types_apart() {
+        let executor = run(concat!(
+            "@memo\n",
+            "fn same(x) { return x }\n",
+            "let one = same(1)\n",
+            "let one_text = same(\"1\")\n",
+            "let list = same([1])\n",
+            "let list_text = same(\"[1]\")",
+        ))
+        .expect("program runs");
+        assert_eq!(variable(&executor, "one"), Value::Number(1.0));
+        assert_eq!(
+            variable(&executor, "one_text"),
+            Value::String(Rc::from("1"))
+        );
+        assert_eq!(
+            variable(&executor, "list"),
+            Value::Array(vec![Value::Number(1.0)])
+        );
+        assert_eq!(
+            variable(&executor, "list_text"),
+            Value::String(Rc::from("[1]"))
+        );
+    }
+
+    #[test]
+    fn memo_tells_same_named_functions_apart() {
+        let executor = run(concat!(
+            "let fs = []\n",
+            "for i in range(0, 2) {\n",
+            "    fn get() { return i }\n",
+            "    fs = fs + [get]\n",
+            "}\n",
+            "@memo\n",
+            "fn call(f) { return f() }\n",
+            "let seen = []\n",
+            "for f in fs {\n",
+            "    seen = seen + [call(f)]\n",
+            "}",
+        ))
+        .expect("program runs");
+        assert_eq!(
+            variable(&executor, "seen"),
+            Value::Array(vec![Value::Number(0.0), Value::Number(1.0)])
+        );
+    }
 }
@@ -1,7 +1,29 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     // Literals
-    Identifier(String),
+    /// Identifiers are interned, so repeated names share one allocation that
+    /// the parser carries into `Expr::Identifier`:
+    ///
+    /// ```
+    /// use std::rc::Rc;
+    /// use weave::ast::{Expr, Stmt};
+    /// use weave::{Lexer, Parser};
+    ///
+    /// let tokens = Lexer::new(b"total + total".to_vec()).tokenize();
+    /// let ast = Parser::new(tokens).parse().expect("program parses");
+    /// let Stmt::Expression(Expr::Binary { left, right, .. }) = &ast.statements[0] else {
+    ///     panic!("expected a binary expression");
+    /// };
+    /// let (Expr::Identifier(a), Expr::Identifier(b)) = (&**left, &**right) else {
+    ///     panic!("expected two identifiers");
+    /// };
+    /// assert!(Rc::ptr_eq(a, b));
+    /// ```
+    Identifier(Rc<str>),
     String(String),
     Number(f64),
 
@@ -14,9 +36,14 @@ pub enum Token {
     RightBracket,
     Comma,
     Dot,
+    DotDot,
+    Ellipsis,
     Semicolon,
     Colon,
     Pipe,
+    Question,
+    FatArrow,
+    At,
 
     // Operators
     Plus,
@@ -31,6 +58,8 @@ pub enum Token {
     LessEqual,
     Greater,
     GreaterEqual,
+    AmpAmp,
+    PipePipe,
 
     // Keywords
     Let,
@@ -39,7 +68,11 @@ pub enum Token {
     Else,
     While,
     For,
+    In,
     Return,
+    Break,
+    Continue,
+    Match,
     True,
     False,
     Nil,
@@ -54,12 +87,70 @@ pub enum Token {
 
     // Special
     Eof,
+    // Text that was recorded in `Lexer::errors`; `tokenize` leaves it out
+    Error,
+}
+
+// A token and the 1-based line and column where it starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned {
+    pub token: Token,
+    pub line: usize,
+    pub column: usize,
+}
+
+// Source text that can't be turned into a token, at a 1-based line and column
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Lex error at {}:{}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+// Something suspicious in the source that still tokenizes, like an integer
+// literal too large to hold exactly
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexWarning {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LexWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Warning at {}:{}: {}",
+            self.line, self.column, self.message
+        )
+    }
 }
 
 pub struct Lexer {
     input: Vec<u8>,
     position: usize,
     current: Option<u8>,
+    // 1-based line of `current`; `\r\n`, `\r` and `\n` each end one line
+    line: usize,
+    // 1-based column of `current`, in characters; a tab moves to the next tab stop
+    column: usize,
+    tab_width: usize,
+    // Match keywords regardless of case (`Let`, `IF`)
+    case_insensitive_keywords: bool,
+    // Identifier names seen so far
+    identifiers: HashMap<String, Rc<str>>,
+    errors: Vec<LexError>,
+    warnings: Vec<LexWarning>,
 }
 
 impl Lexer {
@@ -69,12 +160,54 @@ impl Lexer {
             input,
             position: 0,
             current,
+            line: 1,
+            column: 1,
+            tab_width: 4,
+            case_insensitive_keywords: false,
+            identifiers: HashMap::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
         }
     }
 
-    pub fn tokenize(&mut self) -> Vec<Token> {
+    pub fn with_case_insensitive_keywords(mut self, enabled: bool) -> Self {
+        self.case_insensitive_keywords = enabled;
+        self
+    }
+
+    // Columns between tab stops, so reported columns match what a terminal shows
+    pub fn with_tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width.max(1);
+        self
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    // Problems found by `tokenize`; the tokens are only meaningful when
+    // there are none
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    // Problems found by `tokenize` that don't stop the tokens being used
+    pub fn warnings(&self) -> &[LexWarning] {
+        &self.warnings
+    }
+
+    pub fn tokenize(&mut self) -> Vec<Spanned> {
         let mut tokens = Vec::new();
 
+        // A `#!` interpreter line is only recognized as the very first bytes
+        if self.position == 0 && self.input.starts_with(b"#!") {
+            self.skip_line();
+        }
+
         while self.current.is_some() {
             // Skip whitespace and comments until we find a real token
             loop {
@@ -82,6 +215,8 @@ impl Lexer {
 
                 if self.current == Some(b'#') {
                     self.skip_comment();
+                } else if self.current == Some(b'/') && self.peek() == Some(b'*') {
+                    self.skip_block_comment();
                 } else {
                     break;
                 }
@@ -91,11 +226,22 @@ impl Lexer {
                 break;
             }
 
+            let (line, column) = (self.line, self.column);
             let token = self.next_token();
-            tokens.push(token);
+            if token != Token::Error {
+                tokens.push(Spanned {
+                    token,
+                    line,
+                    column,
+                });
+            }
         }
 
-        tokens.push(Token::Eof);
+        tokens.push(Spanned {
+            token: Token::Eof,
+            line: self.line,
+            column: self.column,
+        });
         tokens
     }
 
@@ -133,7 +279,17 @@ impl Lexer {
             }
             b'.' => {
                 self.advance();
-                Token::Dot
+                if self.current == Some(b'.') {
+                    self.advance();
+                    if self.current == Some(b'.') {
+                        self.advance();
+                        Token::Ellipsis
+                    } else {
+                        Token::DotDot
+                    }
+                } else {
+                    Token::Dot
+                }
             }
             b';' => {
                 self.advance();
@@ -145,7 +301,27 @@ impl Lexer {
             }
             b'|' => {
                 self.advance();
-                Token::Pipe
+                if self.current == Some(b'|') {
+                    self.advance();
+                    Token::PipePipe
+                } else {
+                    Token::Pipe
+                }
+            }
+            // A lone '&' isn't an operator; it falls through to the
+            // unexpected-character error below
+            b'&' if self.peek() == Some(b'&') => {
+                self.advance();
+                self.advance();
+                Token::AmpAmp
+            }
+            b'?' => {
+                self.advance();
+                Token::Question
+            }
+            b'@' => {
+                self.advance();
+                Token::At
             }
             b'+' => {
                 self.advance();
@@ -173,6 +349,9 @@ impl Lexer {
                 if self.current == Some(b'=') {
                     self.advance();
                     Token::EqualEqual
+                } else if self.current == Some(b'>') {
+                    self.advance();
+                    Token::FatArrow
                 } else {
                     Token::Equal
                 }
@@ -207,43 +386,132 @@ impl Lexer {
             b'"' => self.read_string(),
             b'0'..=b'9' => self.read_number(),
             b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.read_identifier(),
-            _ => {
+            // Identifiers are ASCII-only, so a letter like `é` can't start
+            // or continue one
+            byte if !byte.is_ascii() => {
+                let (line, column) = (self.line, self.column);
+                let ch = self.read_char();
+                self.errors.push(LexError {
+                    message: format!(
+                        "Unexpected character '{}'; identifiers may only use ASCII letters, digits and '_'",
+                        ch
+                    ),
+                    line,
+                    column,
+                });
+                Token::Error
+            }
+            byte => {
+                let (line, column, offset) = (self.line, self.column, self.position);
                 self.advance();
-                // For unknown characters, create an identifier (or could error)
-                Token::Identifier(String::from("UNKNOWN"))
+                self.errors.push(LexError {
+                    message: format!("Unexpected character {:?} at byte {}", byte as char, offset),
+                    line,
+                    column,
+                });
+                Token::Error
             }
         }
     }
 
     fn read_string(&mut self) -> Token {
+        let (line, column) = (self.line, self.column);
         self.advance(); // Skip opening quote
-        let mut value = String::new();
+        // Collect raw bytes so multi-byte UTF-8 characters decode whole
+        let mut bytes = Vec::new();
 
         while let Some(ch) = self.current {
             if ch == b'"' {
                 self.advance(); // Skip closing quote
                 break;
             }
-            value.push(ch as char);
+            bytes.push(ch);
             self.advance();
         }
 
+        let value = String::from_utf8(bytes).unwrap_or_else(|err| {
+            self.errors.push(LexError {
+                message: "String literal is not valid UTF-8".to_string(),
+                line,
+                column,
+            });
+            String::from_utf8_lossy(err.as_bytes()).into_owned()
+        });
         Token::String(value)
     }
 
+    // Consume one whole UTF-8 encoded character
+    fn read_char(&mut self) -> char {
+        let start = self.position;
+        self.advance();
+        while self.current.is_some_and(|byte| byte & 0xC0 == 0x80) {
+            self.advance();
+        }
+        String::from_utf8_lossy(&self.input[start..self.position])
+            .chars()
+            .next()
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position + 1).copied()
+    }
+
     fn read_number(&mut self) -> Token {
-        let mut value = String::new();
+        let (line, column) = (self.line, self.column);
+        let mut written = String::new();
 
+        // A number takes at most one '.', and only with a digit after it, so
+        // `1.2.foo` is `1.2` then `.foo` and `1..3` is `1`, `..`, `3`
         while let Some(ch) = self.current {
-            if ch.is_ascii_digit() || ch == b'.' {
-                value.push(ch as char);
+            let decimal_point = ch == b'.'
+                && !written.contains('.')
+                && self.peek().is_some_and(|next| next.is_ascii_digit());
+            if ch.is_ascii_digit() || ch == b'_' || decimal_point {
+                written.push(ch as char);
                 self.advance();
             } else {
                 break;
             }
         }
 
+        // `_` separates digits, as in `1_000`, so it needs a digit either side.
+        // The literal starts with a digit, so there is always a byte before it.
+        let bytes = written.as_bytes();
+        let misplaced = bytes.iter().enumerate().any(|(i, &ch)| {
+            ch == b'_'
+                && !(bytes[i - 1].is_ascii_digit()
+                    && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+        });
+        if misplaced {
+            self.errors.push(LexError {
+                message: format!(
+                    "Number literal '{}' has a misplaced '_'; underscores may only separate digits",
+                    written
+                ),
+                line,
+                column,
+            });
+        }
+        let value = written.replace('_', "");
+
         let num = value.parse::<f64>().unwrap_or(0.0);
+
+        // Numbers are f64, so integer literals beyond 2^53 may silently round
+        if !value.contains('.') {
+            let exact = value.parse::<u128>().is_ok_and(|n| num as u128 == n);
+            if !exact {
+                self.warnings.push(LexWarning {
+                    message: format!(
+                        "integer literal {} cannot be represented exactly and becomes {}",
+                        value, num
+                    ),
+                    line,
+                    column,
+                });
+            }
+        }
+
         Token::Number(num)
     }
 
@@ -260,14 +528,26 @@ impl Lexer {
         }
 
         // Check if identifier is a keyword
-        match value.as_str() {
+        let lowered;
+        let keyword = if self.case_insensitive_keywords {
+            lowered = value.to_ascii_lowercase();
+            lowered.as_str()
+        } else {
+            value.as_str()
+        };
+
+        match keyword {
             "let" => Token::Let,
             "fn" => Token::Fn,
             "if" => Token::If,
             "else" => Token::Else,
             "while" => Token::While,
             "for" => Token::For,
+            "in" => Token::In,
             "return" => Token::Return,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "match" => Token::Match,
             "true" => Token::True,
             "false" => Token::False,
             "nil" => Token::Nil,
@@ -277,10 +557,19 @@ impl Lexer {
             "str" => Token::TypeStr,
             "number" => Token::TypeNumber,
             "bool" => Token::TypeBool,
-            _ => Token::Identifier(value),
+            _ => Token::Identifier(self.intern(value)),
         }
     }
 
+    fn intern(&mut self, name: String) -> Rc<str> {
+        if let Some(interned) = self.identifiers.get(&name) {
+            return Rc::clone(interned);
+        }
+        let interned: Rc<str> = Rc::from(name.as_str());
+        self.identifiers.insert(name, Rc::clone(&interned));
+        interned
+    }
+
     fn skip_whitespace(&mut self) {
         while let Some(ch) = self.current {
             if ch.is_ascii_whitespace() {
@@ -294,16 +583,69 @@ impl Lexer {
     fn skip_comment(&mut self) {
         // Skip line comments starting with '#'
         if self.current == Some(b'#') {
-            while let Some(ch) = self.current {
-                if ch == b'\n' {
-                    break;
+            self.skip_line();
+        }
+    }
+
+    // Skip a `/* ... */` comment. They nest, so `/* a /* b */ c */` is one
+    // comment.
+    fn skip_block_comment(&mut self) {
+        let (line, column) = (self.line, self.column);
+        self.advance(); // '/'
+        self.advance(); // '*'
+
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.current, self.peek()) {
+                (None, _) => {
+                    self.errors.push(LexError {
+                        message: "Block comment is never closed with '*/'".to_string(),
+                        line,
+                        column,
+                    });
+                    return;
                 }
-                self.advance();
+                (Some(b'/'), Some(b'*')) => {
+                    depth += 1;
+                    self.advance();
+                    self.advance();
+                }
+                (Some(b'*'), Some(b'/')) => {
+                    depth -= 1;
+                    self.advance();
+                    self.advance();
+                }
+                _ => self.advance(),
+            }
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(ch) = self.current {
+            if ch == b'\n' || ch == b'\r' {
+                break;
             }
+            self.advance();
         }
     }
 
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.column = 1;
+    }
+
     fn advance(&mut self) {
+        // The `\r` of a `\r\n` pair is counted when the `\n` is passed
+        match self.current {
+            Some(b'\n') => self.new_line(),
+            Some(b'\r') if self.input.get(self.position + 1) != Some(&b'\n') => self.new_line(),
+            Some(b'\r') => {}
+            Some(b'\t') => self.column += self.tab_width - (self.column - 1) % self.tab_width,
+            // UTF-8 continuation bytes are part of the previous character
+            Some(byte) if byte & 0xC0 == 0x80 => {}
+            Some(_) => self.column += 1,
+            None => {}
+        }
         self.position += 1;
         self.current = if self.position < self.input.len() {
             Some(self.input[self.position])
@@ -312,3 +654,82 @@ impl Lexer {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(source: &str) -> (Vec<Token>, Lexer) {
+        let mut lexer = Lexer::new(source.as_bytes().to_vec());
+        let tokens = lexer.tokenize().into_iter().map(|s| s.token).collect();
+        (tokens, lexer)
+    }
+
+    #[test]
+    fn sixteen_digit_integer_round_trips_exactly() {
+        let (tokens, lexer) = lex("1234567890123456");
+        let Token::Number(n) = tokens[0] else {
+            panic!("expected a number");
+        };
+        assert_eq!(n.to_string(), "1234567890123456");
+        assert!(lexer.warnings().is_empty());
+    }
+
+    #[test]
+    fn inexact_integer_is_a_warning_not_output() {
+        let (_, lexer) = lex("let big = 9007199254740993");
+        assert!(lexer.errors().is_empty());
+        let warnings = lexer.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].line, warnings[0].column), (1, 11));
+        assert!(warnings[0].message.contains("9007199254740992"));
+    }
+
+    #[test]
+    fn one_character_operators_at_end_of_input() {
+        for (source, expected) in [("=", Token::Equal), ("!", Token::Bang), ("<", Token::Less)] {
+            let (tokens, lexer) = lex(source);
+            assert!(lexer.errors().is_empty(), "{:?}", lexer.errors());
+            assert_eq!(tokens, vec![expected, Token::Eof]);
+        }
+    }
+
+    #[test]
+    fn keywords_ignore_case_only_when_asked() {
+        let (tokens, _) = lex("IF");
+        assert_eq!(tokens[0], Token::Identifier(Rc::from("IF")));
+
+        let mut lexer = Lexer::new(b"IF".to_vec()).with_case_insensitive_keywords(true);
+        assert_eq!(lexer.tokenize()[0].token, Token::If);
+    }
+
+    #[test]
+    fn mixed_line_endings_each_end_one_line() {
+        let spanned = Lexer::new(b"a\r\nb\rc\nd".to_vec()).tokenize();
+        let lines: Vec<(Token, usize)> = spanned
+            .into_iter()
+            .filter(|s| matches!(s.token, Token::Identifier(_)))
+            .map(|s| (s.token, s.line))
+            .collect();
+        let expected: Vec<(Token, usize)> = ["a", "b", "c", "d"]
+            .into_iter()
+            .zip(1..)
+            .map(|(name, line)| (Token::Identifier(Rc::from(name)), line))
+            .collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn tab_moves_to_the_next_tab_stop() {
+        // The column of `x`, the last token before the end
+        let column = |source: &str, width: usize| {
+            let mut lexer = Lexer::new(source.as_bytes().to_vec()).with_tab_width(width);
+            let tokens = lexer.tokenize();
+            tokens[tokens.len() - 2].column
+        };
+        assert_eq!(column("\tx", 4), 5);
+        assert_eq!(column("ab\tx", 4), 5);
+        assert_eq!(column("abcd\tx", 4), 9);
+        assert_eq!(column("\tx", 8), 9);
+    }
+}
@@ -0,0 +1,34 @@
+//! The Weave pipeline as a library, for embedding: `Lexer` turns source bytes
+//! into tokens, `Parser` builds an `Ast`, `Checker` finds mistakes before
+//! running, and `Executor` runs it. The `weave` binary is a thin CLI on top.
+//!
+//! ```
+//! use weave::{Checker, Executor, Lexer, Parser};
+//!
+//! let mut lexer = Lexer::new(b"print(1 + 2)".to_vec());
+//! let tokens = lexer.tokenize();
+//! assert!(lexer.errors().is_empty());
+//!
+//! let ast = Parser::new(tokens).parse().expect("program parses");
+//! let diagnostics = Checker::new(&ast).check();
+//! assert!(diagnostics.is_empty());
+//!
+//! Executor::new(ast).exec().expect("program runs");
+//! ```
+
+pub mod ast;
+pub mod builtins;
+pub mod bytecode;
+pub mod checker;
+pub mod error_codes;
+pub mod executor;
+pub mod lexer;
+pub mod parser;
+mod random;
+mod scope;
+
+pub use ast::Ast;
+pub use checker::Checker;
+pub use executor::{Executor, RuntimeError, Value};
+pub use lexer::{Lexer, Token};
+pub use parser::Parser;
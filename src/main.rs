@@ -1,13 +1,7 @@
-#![allow(unused)]
-#![allow(dead_code)]
-
 use clap::Parser as ClapParser;
 
-mod ast;
 mod cli;
-mod executor;
-mod lexer;
-mod parser;
+mod repl;
 
 use cli::Cli;
 
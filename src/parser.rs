@@ -1,37 +1,131 @@
-use crate::ast::{Ast, BinaryOp, Expr, Stmt, Type, UnaryOp};
-use crate::lexer::Token;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Ast, BinaryOp, Expr, MatchArm, MatchPattern, Pattern, Stmt, Type, UnaryOp};
+use crate::lexer::{Spanned, Token};
+
+// Positional and named arguments of a call
+type Arguments = (Vec<Expr>, Vec<(Rc<str>, Expr)>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    // Where the token the error was found at starts
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Parse error at {}:{}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<Spanned>,
     position: usize,
+    // Parentheses and square brackets passed but not yet closed; inside
+    // them a logical line may span several source lines
+    open_brackets: usize,
+    // String literals seen so far, so evaluating one never allocates
+    strings: HashSet<Rc<str>>,
+    // Names the parser builds itself, like `Point.new`, shared the way the
+    // lexer shares identifiers
+    identifiers: HashSet<Rc<str>>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<Spanned>) -> Self {
         Parser {
             tokens,
             position: 0,
+            open_brackets: 0,
+            strings: HashSet::new(),
+            identifiers: HashSet::new(),
         }
     }
 
-    pub fn parse(&mut self) -> Ast {
+    fn intern(&mut self, value: String) -> Rc<str> {
+        intern_in(&mut self.strings, value)
+    }
+
+    fn identifier(&mut self, name: String) -> Rc<str> {
+        intern_in(&mut self.identifiers, name)
+    }
+
+    // Parse the whole program, recovering after each syntax error so every
+    // error in the file is reported at once
+    pub fn parse(&mut self) -> Result<Ast, Vec<ParseError>> {
         let mut statements = Vec::new();
+        let mut errors = Vec::new();
 
         while !matches!(self.current_token(), Token::Eof) {
-            statements.push(self.parse_statement());
+            let start = self.position;
+            match self.parse_statement() {
+                Ok(stmt) => statements.push(stmt),
+                Err(error) => {
+                    errors.push(error);
+                    self.synchronize(start);
+                }
+            }
         }
 
-        Ast::new(statements)
+        if errors.is_empty() {
+            Ok(Ast::new(statements))
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Skip to the next statement boundary: just past a ';' or at a keyword
+    // that starts a statement
+    fn synchronize(&mut self, start: usize) {
+        // Always make progress, even if the error was on the first token
+        if self.position == start {
+            self.advance();
+        }
+
+        loop {
+            match self.current_token() {
+                Token::Eof => return,
+                Token::Semicolon => {
+                    self.advance();
+                    return;
+                }
+                Token::Let
+                | Token::Fn
+                | Token::At
+                | Token::If
+                | Token::While
+                | Token::For
+                | Token::Match
+                | Token::Return
+                | Token::Break
+                | Token::Continue
+                | Token::Struct
+                | Token::Type => return,
+                _ => self.advance(),
+            }
+        }
     }
 
     // Statement parsing
-    fn parse_statement(&mut self) -> Stmt {
+    fn parse_statement(&mut self) -> Result<Stmt, ParseError> {
         match self.current_token() {
             Token::Let => self.parse_let(),
             Token::Fn => self.parse_function(),
+            Token::At => self.parse_function_attributes(),
             Token::If => self.parse_if(),
             Token::While => self.parse_while(),
+            Token::For => self.parse_for(),
+            Token::Match => self.parse_match(),
             Token::Return => self.parse_return(),
+            Token::Break | Token::Continue => self.parse_loop_control(),
             Token::LeftBrace => self.parse_block(),
             Token::Struct => self.parse_struct(),
             Token::Type => self.parse_type_alias(),
@@ -39,56 +133,205 @@ impl Parser {
         }
     }
 
-    fn parse_let(&mut self) -> Stmt {
+    fn parse_let(&mut self) -> Result<Stmt, ParseError> {
         self.advance(); // consume 'let'
 
+        if matches!(self.current_token(), Token::LeftBracket) {
+            return self.parse_let_array();
+        }
+
         let name = match self.current_token() {
-            Token::Identifier(n) => n.clone(),
-            _ => panic!("Expected identifier after 'let'"),
+            Token::Identifier(n) => Rc::clone(n),
+            _ => return Err(self.error("Expected identifier after 'let'")),
+        };
+        self.advance();
+
+        if matches!(self.current_token(), Token::LeftBrace) {
+            return self.parse_let_struct(name);
+        }
+
+        // Check for type annotation
+        let type_annotation = if matches!(self.current_token(), Token::Colon) {
+            self.advance(); // consume ':'
+            Some(self.parse_type()?)
+        } else {
+            None
         };
+
+        // Expect '='
+        if !matches!(self.current_token(), Token::Equal) {
+            return Err(self.error("Expected '=' in let statement"));
+        }
+        self.advance();
+
+        let value = self.parse_expression()?;
+
+        // Optional semicolon
+        if matches!(self.current_token(), Token::Semicolon) {
+            self.advance();
+        }
+
+        Ok(Stmt::Let {
+            name,
+            type_annotation,
+            value,
+        })
+    }
+
+    fn parse_let_array(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume '['
+
+        let mut elements = Vec::new();
+        let mut rest = None;
+        while !matches!(self.current_token(), Token::RightBracket | Token::Eof) {
+            if rest.is_some() {
+                return Err(self.error("Rest pattern must be the last element"));
+            }
+
+            match self.current_token().clone() {
+                Token::Identifier(n) => {
+                    elements.push(n);
+                    self.advance();
+                }
+                Token::DotDot => {
+                    self.advance(); // consume '..'
+                    match self.current_token() {
+                        Token::Identifier(n) => rest = Some(Rc::clone(n)),
+                        _ => {
+                            return Err(
+                                self.error("Expected identifier after '..' in array pattern")
+                            );
+                        }
+                    }
+                    self.advance();
+                }
+                _ => return Err(self.error("Expected identifier in array pattern")),
+            }
+
+            if matches!(self.current_token(), Token::Comma) {
+                self.advance();
+            }
+        }
+
+        if !matches!(self.current_token(), Token::RightBracket) {
+            return Err(self.error("Expected ']' at end of array pattern"));
+        }
+        self.advance();
+
+        // Expect '='
+        if !matches!(self.current_token(), Token::Equal) {
+            return Err(self.error("Expected '=' in let statement"));
+        }
+        self.advance();
+
+        let value = self.parse_expression()?;
+
+        // Optional semicolon
+        if matches!(self.current_token(), Token::Semicolon) {
+            self.advance();
+        }
+
+        Ok(Stmt::Destructure {
+            pattern: Pattern::Array { elements, rest },
+            value,
+        })
+    }
+
+    fn parse_let_struct(&mut self, type_name: Rc<str>) -> Result<Stmt, ParseError> {
+        self.advance(); // consume '{'
+
+        let mut fields = Vec::new();
+        while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
+            let field = match self.current_token() {
+                Token::Identifier(n) => Rc::clone(n),
+                _ => return Err(self.error("Expected field name in struct pattern")),
+            };
+            self.advance();
+
+            // `z = 0` gives the field a default
+            let default = if matches!(self.current_token(), Token::Equal) {
+                self.advance(); // consume '='
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+            fields.push((field, default));
+
+            if matches!(self.current_token(), Token::Comma) {
+                self.advance();
+            }
+        }
+
+        if !matches!(self.current_token(), Token::RightBrace) {
+            return Err(self.error("Expected '}' at end of struct pattern"));
+        }
         self.advance();
 
         // Expect '='
         if !matches!(self.current_token(), Token::Equal) {
-            panic!("Expected '=' in let statement");
+            return Err(self.error("Expected '=' in let statement"));
         }
         self.advance();
 
-        let value = self.parse_expression();
+        let value = self.parse_expression()?;
 
         // Optional semicolon
         if matches!(self.current_token(), Token::Semicolon) {
             self.advance();
         }
 
-        Stmt::Let { name, value }
+        Ok(Stmt::Destructure {
+            pattern: Pattern::Struct { type_name, fields },
+            value,
+        })
     }
 
-    fn parse_function(&mut self) -> Stmt {
+    fn parse_function(&mut self) -> Result<Stmt, ParseError> {
         self.advance(); // consume 'fn'
 
-        let name = match self.current_token() {
-            Token::Identifier(n) => n.clone(),
-            _ => panic!("Expected function name"),
+        let mut name = match self.current_token() {
+            Token::Identifier(n) => Rc::clone(n),
+            _ => return Err(self.error("Expected function name")),
         };
         self.advance();
 
+        // `fn Point.new(...)` defines an associated function of a type,
+        // stored under the name "Point.new"
+        if matches!(self.current_token(), Token::Dot) {
+            self.advance();
+            let Token::Identifier(method) = self.current_token() else {
+                return Err(self.error("Expected function name after '.'"));
+            };
+            let qualified = format!("{}.{}", name, method);
+            name = self.identifier(qualified);
+            self.advance();
+        }
+
         // Parse parameters
         if !matches!(self.current_token(), Token::LeftParen) {
-            panic!("Expected '(' after function name");
+            return Err(self.error("Expected '(' after function name"));
         }
         self.advance();
 
         let mut params = Vec::new();
+        let mut variadic = false;
         while !matches!(self.current_token(), Token::RightParen) {
+            if variadic {
+                return Err(self.error("A variadic parameter must be the last parameter"));
+            }
+            if matches!(self.current_token(), Token::Ellipsis) {
+                self.advance(); // consume '...'
+                variadic = true;
+            }
+
             if let Token::Identifier(param) = self.current_token() {
-                let param_name = param.clone();
+                let param_name = Rc::clone(param);
                 self.advance();
 
                 // Check for type annotation
                 let param_type = if matches!(self.current_token(), Token::Colon) {
                     self.advance(); // consume ':'
-                    Some(self.parse_type())
+                    Some(self.parse_type()?)
                 } else {
                     None
                 };
@@ -99,7 +342,7 @@ impl Parser {
                     self.advance();
                 }
             } else {
-                panic!("Expected parameter name");
+                return Err(self.error("Expected parameter name"));
             }
         }
         self.advance(); // consume ')'
@@ -107,155 +350,327 @@ impl Parser {
         // Parse optional return type
         let return_type = if matches!(self.current_token(), Token::Arrow) {
             self.advance(); // consume '->'
-            Some(self.parse_type())
+            Some(self.parse_type()?)
         } else {
             None
         };
 
         // Parse body
         let body = if matches!(self.current_token(), Token::LeftBrace) {
-            match self.parse_block() {
+            match self.parse_block()? {
                 Stmt::Block(stmts) => stmts,
-                _ => panic!("Expected block"),
+                _ => return Err(self.error("Expected block")),
             }
         } else {
-            panic!("Expected function body");
+            return Err(self.error("Expected function body"));
         };
 
-        Stmt::Function {
+        Ok(Stmt::Function {
             name,
             params,
             return_type,
             body,
+            memo: false,
+            variadic,
+        })
+    }
+
+    // `@memo fn ...`
+    fn parse_function_attributes(&mut self) -> Result<Stmt, ParseError> {
+        let mut memo = false;
+        while matches!(self.current_token(), Token::At) {
+            self.advance(); // consume '@'
+            match self.current_token() {
+                Token::Identifier(attr) if &**attr == "memo" => memo = true,
+                Token::Identifier(attr) => {
+                    return Err(self.error(format!("Unknown function attribute '@{}'", attr)));
+                }
+                _ => return Err(self.error("Expected attribute name after '@'")),
+            }
+            self.advance();
+        }
+
+        if !matches!(self.current_token(), Token::Fn) {
+            return Err(self.error("Expected 'fn' after function attributes"));
+        }
+        let mut function = self.parse_function()?;
+        if let Stmt::Function { memo: is_memo, .. } = &mut function {
+            *is_memo = memo;
         }
+        Ok(function)
     }
 
-    fn parse_if(&mut self) -> Stmt {
+    fn parse_if(&mut self) -> Result<Stmt, ParseError> {
         self.advance(); // consume 'if'
 
-        let condition = self.parse_expression();
+        let condition = self.parse_expression()?;
 
         let then_branch = if matches!(self.current_token(), Token::LeftBrace) {
-            match self.parse_block() {
+            match self.parse_block()? {
                 Stmt::Block(stmts) => stmts,
-                _ => panic!("Expected block"),
+                _ => return Err(self.error("Expected block")),
             }
         } else {
-            panic!("Expected '{{' after if condition");
+            return Err(self.error("Expected '{' after if condition"));
         };
 
         let else_branch = if matches!(self.current_token(), Token::Else) {
             self.advance();
-            Some(match self.parse_block() {
+            Some(match self.parse_block()? {
                 Stmt::Block(stmts) => stmts,
-                _ => panic!("Expected block"),
+                _ => return Err(self.error("Expected block")),
             })
         } else {
             None
         };
 
-        Stmt::If {
+        Ok(Stmt::If {
             condition,
             then_branch,
             else_branch,
-        }
+        })
     }
 
-    fn parse_while(&mut self) -> Stmt {
+    fn parse_while(&mut self) -> Result<Stmt, ParseError> {
         self.advance(); // consume 'while'
 
-        let condition = self.parse_expression();
+        let condition = self.parse_expression()?;
+
+        let body = if matches!(self.current_token(), Token::LeftBrace) {
+            match self.parse_block()? {
+                Stmt::Block(stmts) => stmts,
+                _ => return Err(self.error("Expected block")),
+            }
+        } else {
+            return Err(self.error("Expected '{' after while condition"));
+        };
+
+        Ok(Stmt::While { condition, body })
+    }
+
+    fn parse_match(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'match'
+
+        let subject = self.parse_expression()?;
+
+        if !matches!(self.current_token(), Token::LeftBrace) {
+            return Err(self.error("Expected '{' after match subject"));
+        }
+        self.advance();
+
+        let mut arms = Vec::new();
+        while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
+            let pattern = match self.current_token().clone() {
+                Token::Identifier(name) if &*name == "_" => MatchPattern::Wildcard,
+                Token::Identifier(name) => MatchPattern::Binding(name),
+                Token::String(s) => MatchPattern::Literal(Expr::String(self.intern(s))),
+                Token::Number(n) => MatchPattern::Literal(Expr::Number(n)),
+                Token::True => MatchPattern::Literal(Expr::Boolean(true)),
+                Token::False => MatchPattern::Literal(Expr::Boolean(false)),
+                Token::Nil => MatchPattern::Literal(Expr::Nil),
+                _ => return Err(self.error("Expected a literal, a name or '_' in match arm")),
+            };
+            self.advance();
+
+            if !matches!(self.current_token(), Token::FatArrow) {
+                return Err(self.error("Expected '=>' after match pattern"));
+            }
+            self.advance();
+
+            // An arm is either a block or a single statement
+            let body = if matches!(self.current_token(), Token::LeftBrace) {
+                match self.parse_block()? {
+                    Stmt::Block(stmts) => stmts,
+                    _ => return Err(self.error("Expected block")),
+                }
+            } else {
+                vec![self.parse_statement()?]
+            };
+
+            // Optional comma between arms
+            if matches!(self.current_token(), Token::Comma) {
+                self.advance();
+            }
+
+            arms.push(MatchArm { pattern, body });
+        }
+
+        if !matches!(self.current_token(), Token::RightBrace) {
+            return Err(self.error("Expected '}' at end of match"));
+        }
+        self.advance();
+
+        Ok(Stmt::Match { subject, arms })
+    }
+
+    fn parse_for(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'for'
+
+        let variable = match self.current_token() {
+            Token::Identifier(n) => Rc::clone(n),
+            _ => return Err(self.error("Expected loop variable after 'for'")),
+        };
+        self.advance();
+
+        // Expect 'in'
+        if !matches!(self.current_token(), Token::In) {
+            return Err(self.error("Expected 'in' after loop variable"));
+        }
+        self.advance();
+
+        let iterable = self.parse_expression()?;
 
         let body = if matches!(self.current_token(), Token::LeftBrace) {
-            match self.parse_block() {
+            match self.parse_block()? {
                 Stmt::Block(stmts) => stmts,
-                _ => panic!("Expected block"),
+                _ => return Err(self.error("Expected block")),
             }
         } else {
-            panic!("Expected '{{' after while condition");
+            return Err(self.error("Expected '{' after for iterable"));
         };
 
-        Stmt::While { condition, body }
+        Ok(Stmt::For {
+            variable,
+            iterable,
+            body,
+        })
     }
 
-    fn parse_return(&mut self) -> Stmt {
+    fn parse_return(&mut self) -> Result<Stmt, ParseError> {
         self.advance(); // consume 'return'
 
         let value = if matches!(self.current_token(), Token::Semicolon | Token::RightBrace) {
             None
         } else {
-            Some(self.parse_expression())
+            Some(self.parse_expression()?)
+        };
+
+        if matches!(self.current_token(), Token::Semicolon) {
+            self.advance();
+        }
+
+        Ok(Stmt::Return(value))
+    }
+
+    fn parse_loop_control(&mut self) -> Result<Stmt, ParseError> {
+        let stmt = match self.current_token() {
+            Token::Break => Stmt::Break,
+            _ => Stmt::Continue,
         };
+        self.advance(); // consume 'break' or 'continue'
 
         if matches!(self.current_token(), Token::Semicolon) {
             self.advance();
         }
 
-        Stmt::Return(value)
+        Ok(stmt)
     }
 
-    fn parse_block(&mut self) -> Stmt {
+    fn parse_block(&mut self) -> Result<Stmt, ParseError> {
         self.advance(); // consume '{'
 
         let mut statements = Vec::new();
 
         while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
-            statements.push(self.parse_statement());
+            statements.push(self.parse_statement()?);
         }
 
         if !matches!(self.current_token(), Token::RightBrace) {
-            panic!("Expected '}}' at end of block");
+            return Err(self.error("Expected '}' at end of block"));
         }
         self.advance(); // consume '}'
 
-        Stmt::Block(statements)
+        Ok(Stmt::Block(statements))
     }
 
-    fn parse_expression_statement(&mut self) -> Stmt {
-        let expr = self.parse_expression();
+    fn parse_expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expr = self.parse_expression()?;
+
+        // `parse_expression` stops at a single '=', which only `==` consumes
+        let stmt = if matches!(self.current_token(), Token::Equal) {
+            if let Expr::Identifier(name) = expr {
+                self.advance(); // consume '='
+                Stmt::Assign {
+                    name,
+                    value: self.parse_expression()?,
+                }
+            } else {
+                let Some((target, fields)) = field_path(&expr) else {
+                    return Err(self.error(
+                        "Only a variable or a struct field, like 'x' or 'p.x', can be assigned to",
+                    ));
+                };
+                self.advance(); // consume '='
+                Stmt::FieldAssign {
+                    target,
+                    fields,
+                    value: self.parse_expression()?,
+                }
+            }
+        } else {
+            Stmt::Expression(expr)
+        };
 
         // Optional semicolon
         if matches!(self.current_token(), Token::Semicolon) {
             self.advance();
         }
 
-        Stmt::Expression(expr)
+        Ok(stmt)
     }
 
-    fn parse_struct(&mut self) -> Stmt {
+    fn parse_struct(&mut self) -> Result<Stmt, ParseError> {
         self.advance(); // consume 'struct'
 
         let name = match self.current_token() {
-            Token::Identifier(n) => n.clone(),
-            _ => panic!("Expected struct name"),
+            Token::Identifier(n) => n.to_string(),
+            _ => return Err(self.error("Expected struct name")),
         };
         self.advance();
 
         // Expect '{'
         if !matches!(self.current_token(), Token::LeftBrace) {
-            panic!("Expected '{{' after struct name");
+            return Err(self.error("Expected '{' after struct name"));
         }
         self.advance();
 
         // Parse fields
         let mut fields = Vec::new();
+        let mut skip_eq = Vec::new();
         while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
+            // Field attributes
+            let mut skip_field_eq = false;
+            while matches!(self.current_token(), Token::At) {
+                self.advance(); // consume '@'
+                match self.current_token() {
+                    Token::Identifier(attr) if &**attr == "skip_eq" => skip_field_eq = true,
+                    Token::Identifier(attr) => {
+                        return Err(self.error(format!("Unknown field attribute '@{}'", attr)));
+                    }
+                    _ => return Err(self.error("Expected attribute name after '@'")),
+                }
+                self.advance();
+            }
+
             // Field name
             let field_name = match self.current_token() {
-                Token::Identifier(n) => n.clone(),
-                _ => panic!("Expected field name"),
+                Token::Identifier(n) => n.to_string(),
+                _ => return Err(self.error("Expected field name")),
             };
             self.advance();
 
             // Expect ':'
             if !matches!(self.current_token(), Token::Colon) {
-                panic!("Expected ':' after field name");
+                return Err(self.error("Expected ':' after field name"));
             }
             self.advance();
 
             // Parse type
-            let field_type = self.parse_type();
+            let field_type = self.parse_type()?;
 
+            if skip_field_eq {
+                skip_eq.push(field_name.clone());
+            }
             fields.push((field_name, field_type));
 
             // Optional comma or newline (we just skip to next field)
@@ -265,38 +680,50 @@ impl Parser {
         }
 
         if !matches!(self.current_token(), Token::RightBrace) {
-            panic!("Expected '}}' at end of struct");
+            return Err(self.error("Expected '}' at end of struct"));
         }
         self.advance();
 
-        Stmt::Struct { name, fields }
+        Ok(Stmt::Struct {
+            name,
+            fields,
+            skip_eq,
+        })
     }
 
-    fn parse_type_alias(&mut self) -> Stmt {
+    fn parse_type_alias(&mut self) -> Result<Stmt, ParseError> {
         self.advance(); // consume 'type'
 
         let name = match self.current_token() {
-            Token::Identifier(n) => n.clone(),
-            _ => panic!("Expected type alias name"),
+            Token::Identifier(n) => n.to_string(),
+            _ => return Err(self.error("Expected type alias name")),
         };
         self.advance();
 
         // Expect '='
         if !matches!(self.current_token(), Token::Equal) {
-            panic!("Expected '=' in type alias");
+            return Err(self.error("Expected '=' in type alias"));
         }
         self.advance();
 
         // Parse union variants (string literals separated by |)
         let mut variants = Vec::new();
+        // Reported once the whole alias is consumed, pointing at the repeat
+        let mut duplicate = None;
 
         loop {
             match self.current_token() {
                 Token::String(s) => {
+                    if variants.contains(s) && duplicate.is_none() {
+                        duplicate = Some(self.error(format!(
+                            "Variant {:?} appears more than once in type {}",
+                            s, name
+                        )));
+                    }
                     variants.push(s.clone());
                     self.advance();
                 }
-                _ => panic!("Expected string literal in type union"),
+                _ => return Err(self.error("Expected string literal in type union")),
             }
 
             if matches!(self.current_token(), Token::Pipe) {
@@ -307,16 +734,24 @@ impl Parser {
         }
 
         if variants.is_empty() {
-            panic!("Type alias must have at least one variant");
+            return Err(self.error("Type alias must have at least one variant"));
+        }
+        if let Some(error) = duplicate {
+            return Err(error);
         }
 
-        Stmt::TypeAlias { name, variants }
+        Ok(Stmt::TypeAlias { name, variants })
+    }
+
+    fn parse_struct_literal(&mut self, name: Rc<str>) -> Result<Expr, ParseError> {
+        let fields = self.parse_struct_literal_fields()?;
+        Ok(Expr::StructLiteral { name, fields })
     }
 
-    fn parse_struct_literal(&mut self, name: String) -> Expr {
+    fn parse_struct_literal_fields(&mut self) -> Result<Vec<(Rc<str>, Expr)>, ParseError> {
         // Expect '{'
         if !matches!(self.current_token(), Token::LeftBrace) {
-            panic!("Expected '{{' for struct literal");
+            return Err(self.error("Expected '{' for struct literal"));
         }
         self.advance();
 
@@ -325,19 +760,19 @@ impl Parser {
         while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
             // Field name
             let field_name = match self.current_token() {
-                Token::Identifier(n) => n.clone(),
-                _ => panic!("Expected field name"),
+                Token::Identifier(n) => Rc::clone(n),
+                _ => return Err(self.error("Expected field name")),
             };
             self.advance();
 
             // Expect ':'
             if !matches!(self.current_token(), Token::Colon) {
-                panic!("Expected ':' after field name in struct literal");
+                return Err(self.error("Expected ':' after field name in struct literal"));
             }
             self.advance();
 
             // Parse value expression
-            let value = self.parse_expression();
+            let value = self.parse_expression()?;
 
             fields.push((field_name, value));
 
@@ -348,35 +783,102 @@ impl Parser {
         }
 
         if !matches!(self.current_token(), Token::RightBrace) {
-            panic!("Expected '}}' at end of struct literal");
+            return Err(self.error("Expected '}' at end of struct literal"));
         }
         self.advance();
 
-        Expr::StructLiteral { name, fields }
+        Ok(fields)
     }
 
-    fn parse_type(&mut self) -> Type {
+    fn parse_array_literal(&mut self) -> Result<Expr, ParseError> {
+        self.advance(); // consume '['
+
+        let mut elements = Vec::new();
+        while !matches!(self.current_token(), Token::RightBracket | Token::Eof) {
+            elements.push(self.parse_expression()?);
+
+            // Optional comma
+            if matches!(self.current_token(), Token::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+
+        if !matches!(self.current_token(), Token::RightBracket) {
+            return Err(self.error("Expected ']' at end of array literal"));
+        }
+        self.advance();
+
+        Ok(Expr::Array(elements))
+    }
+
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
         let typ = match self.current_token() {
             Token::TypeStr => Type::Str,
             Token::TypeNumber => Type::Number,
             Token::TypeBool => Type::Bool,
             Token::Identifier(name) => {
                 // Custom type (either struct or type alias)
-                Type::Custom(name.clone())
+                Type::Custom(name.to_string())
+            }
+            _ => {
+                return Err(self.error(format!(
+                    "Expected type annotation, got {:?}",
+                    self.current_token()
+                )));
             }
-            _ => panic!("Expected type annotation, got {:?}", self.current_token()),
         };
         self.advance();
-        typ
+
+        // Optional suffix: `T?`
+        if matches!(self.current_token(), Token::Question) {
+            self.advance();
+            return Ok(Type::Optional(Box::new(typ)));
+        }
+
+        Ok(typ)
     }
 
     // Expression parsing (with precedence)
-    fn parse_expression(&mut self) -> Expr {
-        self.parse_equality()
+    fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        self.parse_logic_or()
+    }
+
+    fn parse_logic_or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_logic_and()?;
+
+        while matches!(self.current_token(), Token::PipePipe) {
+            self.advance();
+            let right = self.parse_logic_and()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: BinaryOp::Or,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
     }
 
-    fn parse_equality(&mut self) -> Expr {
-        let mut expr = self.parse_comparison();
+    fn parse_logic_and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_equality()?;
+
+        while matches!(self.current_token(), Token::AmpAmp) {
+            self.advance();
+            let right = self.parse_equality()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: BinaryOp::And,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_equality(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_comparison()?;
 
         while matches!(
             self.current_token(),
@@ -388,7 +890,7 @@ impl Parser {
                 _ => unreachable!(),
             };
             self.advance();
-            let right = self.parse_comparison();
+            let right = self.parse_comparison()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -396,11 +898,11 @@ impl Parser {
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn parse_comparison(&mut self) -> Expr {
-        let mut expr = self.parse_term();
+    fn parse_comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_term()?;
 
         while matches!(
             self.current_token(),
@@ -414,7 +916,7 @@ impl Parser {
                 _ => unreachable!(),
             };
             self.advance();
-            let right = self.parse_term();
+            let right = self.parse_term()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -422,11 +924,11 @@ impl Parser {
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn parse_term(&mut self) -> Expr {
-        let mut expr = self.parse_factor();
+    fn parse_term(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_factor()?;
 
         while matches!(self.current_token(), Token::Plus | Token::Minus) {
             let operator = match self.current_token() {
@@ -435,7 +937,7 @@ impl Parser {
                 _ => unreachable!(),
             };
             self.advance();
-            let right = self.parse_factor();
+            let right = self.parse_factor()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -443,11 +945,11 @@ impl Parser {
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn parse_factor(&mut self) -> Expr {
-        let mut expr = self.parse_unary();
+    fn parse_factor(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_unary()?;
 
         while matches!(self.current_token(), Token::Star | Token::Slash) {
             let operator = match self.current_token() {
@@ -456,7 +958,7 @@ impl Parser {
                 _ => unreachable!(),
             };
             self.advance();
-            let right = self.parse_unary();
+            let right = self.parse_unary()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -464,46 +966,54 @@ impl Parser {
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn parse_unary(&mut self) -> Expr {
-        if matches!(self.current_token(), Token::Bang | Token::Minus) {
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        if matches!(
+            self.current_token(),
+            Token::Bang | Token::Minus | Token::Plus
+        ) {
             let operator = match self.current_token() {
                 Token::Bang => UnaryOp::Not,
                 Token::Minus => UnaryOp::Negate,
+                Token::Plus => UnaryOp::Plus,
                 _ => unreachable!(),
             };
             self.advance();
-            let operand = self.parse_unary();
-            return Expr::Unary {
+            let operand = self.parse_unary()?;
+            return Ok(Expr::Unary {
                 operator,
                 operand: Box::new(operand),
-            };
+            });
         }
 
         self.parse_call()
     }
 
-    fn parse_call(&mut self) -> Expr {
-        let mut expr = self.parse_primary();
+    fn parse_call(&mut self) -> Result<Expr, ParseError> {
+        let parenthesized = matches!(self.current_token(), Token::LeftParen);
+        let mut expr = self.parse_primary()?;
 
         // Special case: if we just parsed an identifier and the current token is LeftBrace,
         // check if it's actually a struct literal by peeking inside
-        if let Expr::Identifier(name) = &expr {
-            if matches!(self.current_token(), Token::LeftBrace) {
-                // Peek ahead to see if this looks like a struct literal
-                // Struct literals have the pattern: { identifier: ...
-                // If we see anything else after {, it's not a struct literal
-                let next_token = self.peek(1);
-                let looks_like_struct = matches!(next_token, Token::Identifier(_));
-
-                if looks_like_struct {
-                    // Check if there's a colon after the identifier
-                    let after_id = self.peek(2);
-                    if matches!(after_id, Token::Colon) {
-                        return self.parse_struct_literal(name.clone());
-                    }
+        // A parenthesized name like `(x) {` is never a struct literal
+        if let Expr::Identifier(name) = &expr
+            && !parenthesized
+            && matches!(self.current_token(), Token::LeftBrace)
+        {
+            // Peek ahead to see if this looks like a struct literal
+            // Struct literals have the pattern: { identifier: ...
+            // If we see anything else after {, it's not a struct literal
+            let next_token = self.peek(1);
+            let looks_like_struct = matches!(next_token, Token::Identifier(_));
+
+            if looks_like_struct {
+                // Check if there's a colon after the identifier
+                let after_id = self.peek(2);
+                if matches!(after_id, Token::Colon) {
+                    // Fall through so fields and methods can chain off the literal
+                    expr = self.parse_struct_literal(Rc::clone(name))?;
                 }
             }
         }
@@ -513,67 +1023,35 @@ impl Parser {
                 Token::LeftParen => {
                     // Function call
                     self.advance();
-                    let mut arguments = Vec::new();
-
-                    if !matches!(self.current_token(), Token::RightParen) {
-                        loop {
-                            arguments.push(self.parse_expression());
-
-                            if matches!(self.current_token(), Token::Comma) {
-                                self.advance();
-                            } else {
-                                break;
-                            }
-                        }
-                    }
-
-                    if !matches!(self.current_token(), Token::RightParen) {
-                        panic!("Expected ')' after arguments");
-                    }
-                    self.advance();
+                    let (arguments, named_arguments) = self.parse_arguments()?;
 
                     expr = Expr::Call {
                         callee: Box::new(expr),
                         arguments,
+                        named_arguments,
                     };
                 }
                 Token::Dot => {
                     // Field access or method call
                     self.advance();
                     let field = match self.current_token() {
-                        Token::Identifier(name) => name.clone(),
-                        _ => panic!("Expected field name after '.'"),
+                        Token::Identifier(name) => Rc::clone(name),
+                        _ => return Err(self.error("Expected field name after '.'")),
                     };
                     self.advance();
 
                     // Check if this is a method call (followed by '(')
                     if matches!(self.current_token(), Token::LeftParen) {
-                        // Method call: transform to function call with receiver as first arg
+                        // Method call: kept apart from a free call so a nil
+                        // receiver can be reported
                         self.advance(); // consume '('
 
-                        let mut arguments = vec![expr]; // receiver is first argument
-
-                        if !matches!(self.current_token(), Token::RightParen) {
-                            loop {
-                                arguments.push(self.parse_expression());
-
-                                if matches!(self.current_token(), Token::Comma) {
-                                    self.advance();
-                                } else {
-                                    break;
-                                }
-                            }
-                        }
-
-                        if !matches!(self.current_token(), Token::RightParen) {
-                            panic!("Expected ')' after arguments");
-                        }
-                        self.advance();
-
-                        // Create a function call with the method name
-                        expr = Expr::Call {
-                            callee: Box::new(Expr::Identifier(field)),
+                        let (arguments, named_arguments) = self.parse_arguments()?;
+                        expr = Expr::MethodCall {
+                            receiver: Box::new(expr),
+                            method: field,
                             arguments,
+                            named_arguments,
                         };
                     } else {
                         // Regular field access
@@ -583,57 +1061,303 @@ impl Parser {
                         };
                     }
                 }
+                Token::LeftBracket => {
+                    // Index access
+                    self.advance();
+                    let index = self.parse_expression()?;
+                    if !matches!(self.current_token(), Token::RightBracket) {
+                        return Err(self.error("Expected ']' after index"));
+                    }
+                    self.advance();
+
+                    expr = Expr::Index {
+                        object: Box::new(expr),
+                        index: Box::new(index),
+                    };
+                }
                 _ => break,
             }
         }
 
-        expr
+        Ok(expr)
+    }
+
+    // Parse call arguments up to and including the closing ')'.
+    // Named arguments (`name: value`) must come after all positional ones.
+    fn parse_arguments(&mut self) -> Result<Arguments, ParseError> {
+        let mut arguments = Vec::new();
+        let mut named_arguments = Vec::new();
+
+        if !matches!(self.current_token(), Token::RightParen) {
+            loop {
+                if let Token::Identifier(name) = self.current_token()
+                    && matches!(self.peek(1), Token::Colon)
+                {
+                    let name = Rc::clone(name);
+                    self.advance(); // consume name
+                    self.advance(); // consume ':'
+                    named_arguments.push((name, self.parse_expression()?));
+                } else if named_arguments.is_empty() {
+                    if matches!(self.current_token(), Token::Ellipsis) {
+                        self.advance(); // consume '...'
+                        arguments.push(Expr::Spread(Box::new(self.parse_expression()?)));
+                    } else {
+                        arguments.push(self.parse_expression()?);
+                    }
+                } else {
+                    return Err(self.error("Positional argument after named argument"));
+                }
+
+                if matches!(self.current_token(), Token::Comma) {
+                    self.advance();
+                } else {
+                    break;
+                }
+                // A trailing comma before ')'
+                if matches!(self.current_token(), Token::RightParen) {
+                    break;
+                }
+            }
+        }
+
+        if !matches!(self.current_token(), Token::RightParen) {
+            return Err(self.error("Expected ')' after arguments"));
+        }
+        self.advance();
+
+        Ok((arguments, named_arguments))
     }
 
-    fn parse_primary(&mut self) -> Expr {
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
         let expr = match self.current_token().clone() {
             Token::Number(n) => Expr::Number(n),
-            Token::String(s) => Expr::String(s),
+            Token::String(s) => {
+                // Adjacent string literals join into one, like "foo" "bar",
+                // when they are on the same logical line
+                let mut value = s;
+                let mut line = self.line();
+                self.advance();
+                while let Token::String(next) = self.current_token() {
+                    if self.line() != line && self.open_brackets == 0 {
+                        break;
+                    }
+                    value.push_str(next);
+                    line = self.line();
+                    self.advance();
+                }
+                return Ok(Expr::String(self.intern(value)));
+            }
             Token::True => Expr::Boolean(true),
             Token::False => Expr::Boolean(false),
             Token::Nil => Expr::Nil,
+            Token::LeftBracket => return self.parse_array_literal(),
+            // `{ field: ... }` is an unnamed struct literal
+            Token::LeftBrace
+                if matches!(self.peek(1), Token::Identifier(_))
+                    && matches!(self.peek(2), Token::Colon) =>
+            {
+                let fields = self.parse_struct_literal_fields()?;
+                return Ok(Expr::AnonymousStruct { fields });
+            }
             Token::Identifier(name) => {
                 // Check if this might be a struct literal
                 // We peek ahead to see if there's a LeftBrace after this identifier
                 // But we need to be smarter - only treat as struct if we're at statement level
                 Expr::Identifier(name)
             }
+            // The type name `bool` doubles as the conversion built-in
+            Token::TypeBool if matches!(self.peek(1), Token::LeftParen) => {
+                Expr::Identifier(self.identifier("bool".to_string()))
+            }
             Token::LeftParen => {
                 self.advance();
-                let expr = self.parse_expression();
+                let expr = self.parse_expression()?;
                 if !matches!(self.current_token(), Token::RightParen) {
-                    panic!("Expected ')' after expression");
+                    return Err(self.error("Expected ')' after expression"));
                 }
                 self.advance();
-                return Expr::Grouping(Box::new(expr));
+                // Precedence is already encoded in the tree, so no grouping node is needed
+                return Ok(expr);
             }
-            _ => panic!("Unexpected token: {:?}", self.current_token()),
+            _ => return Err(self.error(format!("Unexpected token: {:?}", self.current_token()))),
         };
 
         self.advance();
-        expr
+        Ok(expr)
     }
 
     // Helper methods
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        // Past the end, point at the last token (the lexer always ends with Eof)
+        let (line, column) = self
+            .tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or((1, 1), |spanned| (spanned.line, spanned.column));
+        ParseError {
+            message: message.into(),
+            line,
+            column,
+        }
+    }
+
     fn current_token(&self) -> &Token {
-        self.tokens.get(self.position).unwrap_or(&Token::Eof)
+        self.tokens
+            .get(self.position)
+            .map_or(&Token::Eof, |spanned| &spanned.token)
     }
 
     fn advance(&mut self) {
+        match self.current_token() {
+            Token::LeftParen | Token::LeftBracket => self.open_brackets += 1,
+            Token::RightParen | Token::RightBracket => {
+                self.open_brackets = self.open_brackets.saturating_sub(1)
+            }
+            _ => {}
+        }
         if self.position < self.tokens.len() {
             self.position += 1;
         }
     }
 
-    #[allow(dead_code)]
+    // The source line of the current token
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |spanned| spanned.line)
+    }
+
     fn peek(&self, offset: usize) -> &Token {
         self.tokens
             .get(self.position + offset)
-            .unwrap_or(&Token::Eof)
+            .map_or(&Token::Eof, |spanned| &spanned.token)
+    }
+}
+
+// The variable and field names of `a.b.c`, or None for anything else
+fn field_path(expr: &Expr) -> Option<(Rc<str>, Vec<Rc<str>>)> {
+    let Expr::FieldAccess { object, field } = expr else {
+        return None;
+    };
+    let (target, mut fields) = match object.as_ref() {
+        Expr::Identifier(name) => (name.clone(), Vec::new()),
+        inner => field_path(inner)?,
+    };
+    fields.push(field.clone());
+    Some((target, fields))
+}
+
+fn intern_in(table: &mut HashSet<Rc<str>>, value: String) -> Rc<str> {
+    if let Some(interned) = table.get(value.as_str()) {
+        return Rc::clone(interned);
+    }
+    let interned: Rc<str> = Rc::from(value);
+    table.insert(Rc::clone(&interned));
+    interned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lexer;
+
+    fn parse(source: &str) -> Ast {
+        let tokens = Lexer::new(source.as_bytes().to_vec()).tokenize();
+        Parser::new(tokens).parse().expect("program parses")
+    }
+
+    #[test]
+    fn binding_and_use_share_one_name() {
+        let ast = parse("let total = 1\nprint(total)");
+        let Stmt::Let { name, .. } = &ast.statements[0] else {
+            panic!("expected a let");
+        };
+        let Stmt::Expression(Expr::Call { arguments, .. }) = &ast.statements[1] else {
+            panic!("expected a call");
+        };
+        let Expr::Identifier(used) = &arguments[0] else {
+            panic!("expected an identifier");
+        };
+        assert!(Rc::ptr_eq(name, used));
+    }
+
+    #[test]
+    fn parentheses_leave_no_node_behind() {
+        assert_eq!(parse("let x = ((1))"), parse("let x = 1"));
+
+        // The grouping survives only in the shape of the tree
+        let grouped = parse("let x = (1 + 2) * 3");
+        let Stmt::Let {
+            value: Expr::Binary { left, operator, .. },
+            ..
+        } = &grouped.statements[0]
+        else {
+            panic!("expected a binary let");
+        };
+        assert_eq!(*operator, BinaryOp::Multiply);
+        assert!(matches!(
+            **left,
+            Expr::Binary {
+                operator: BinaryOp::Add,
+                ..
+            }
+        ));
+
+        let mut executor = crate::Executor::new(grouped);
+        executor.exec().expect("program runs");
+        assert_eq!(
+            executor
+                .variables()
+                .into_iter()
+                .find(|(name, _)| *name == "x"),
+            Some(("x", &crate::Value::Number(9.0)))
+        );
+    }
+
+    #[test]
+    fn bool_call_names_an_identifier_not_a_string_literal() {
+        let ast = parse("let s = \"bool\"\nlet b = bool(1)");
+        let Stmt::Let {
+            value: Expr::String(literal),
+            ..
+        } = &ast.statements[0]
+        else {
+            panic!("expected a string let");
+        };
+        let Stmt::Let {
+            value: Expr::Call { callee, .. },
+            ..
+        } = &ast.statements[1]
+        else {
+            panic!("expected a call let");
+        };
+        let Expr::Identifier(name) = &**callee else {
+            panic!("expected an identifier callee");
+        };
+        assert_eq!(&**name, "bool");
+        assert!(!Rc::ptr_eq(name, literal));
+    }
+
+    #[test]
+    fn adjacent_strings_join_only_on_one_logical_line() {
+        let joined = Expr::String(Rc::from("foobar"));
+        let value = |ast: &Ast, index: usize| match &ast.statements[index] {
+            Stmt::Let { value, .. } => value.clone(),
+            Stmt::Expression(value) => value.clone(),
+            _ => panic!("expected a let or an expression"),
+        };
+
+        let ast = parse("let s = \"foo\" \"bar\"");
+        assert_eq!(value(&ast, 0), joined);
+
+        let ast = parse("let s = (\"foo\"\n    \"bar\")");
+        assert_eq!(value(&ast, 0), joined);
+
+        // A string on the next line is a statement of its own
+        let ast = parse("let s = \"foo\"\n\"bar\"");
+        assert_eq!(ast.statements.len(), 2);
+        assert_eq!(value(&ast, 0), Expr::String(Rc::from("foo")));
+        assert_eq!(value(&ast, 1), Expr::String(Rc::from("bar")));
     }
 }
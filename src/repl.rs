@@ -0,0 +1,218 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use weave::ast::Ast;
+use weave::checker::{Checker, Severity};
+use weave::executor::{Executor, Value};
+use weave::lexer::{Lexer, Spanned, Token};
+use weave::parser::Parser;
+
+const PROMPT: &str = "> ";
+// Shown while an input is still open, e.g. after `fn foo() {`
+const CONTINUATION_PROMPT: &str = "... ";
+// Kept in the home directory across sessions
+const HISTORY_FILE: &str = ".weave_history";
+
+const HELP: &str = "\
+.help      Show this list
+.vars      List the variables defined so far
+.clear     Forget every definition and start over
+.history   Show the lines entered, including earlier sessions
+.exit      Leave the REPL";
+
+// What a line starting with `.` asks the loop to do
+enum Command {
+    Continue,
+    Exit,
+}
+
+// Read-eval-print loop sharing one executor across inputs
+pub fn start() {
+    let mut executor = Executor::new(Ast::new(Vec::new()));
+    let mut history = History::load();
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        print!("{}", prompt);
+        io::stdout().flush().ok();
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        if input.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        history.add(&line);
+
+        // Commands are only recognised at the start of an input
+        if input.is_empty() && line.trim_start().starts_with('.') {
+            match run_command(line.trim(), &mut executor, &history) {
+                Command::Continue => {}
+                Command::Exit => break,
+            }
+            continue;
+        }
+        input.push_str(&line);
+        input.push('\n');
+
+        let mut lexer = Lexer::new(input.clone().into_bytes());
+        let tokens = lexer.tokenize();
+        if is_incomplete(&tokens) {
+            continue;
+        }
+        input.clear();
+        if !lexer.errors().is_empty() {
+            for error in lexer.errors() {
+                eprintln!("{}", error);
+            }
+            continue;
+        }
+        for warning in lexer.warnings() {
+            eprintln!("{}", warning);
+        }
+
+        let ast = match Parser::new(tokens).parse() {
+            Ok(ast) => ast,
+            Err(errors) => {
+                for error in &errors {
+                    eprintln!("{}", error);
+                }
+                continue;
+            }
+        };
+
+        let diagnostics = Checker::new(&ast)
+            .with_known_names(executor.variable_names())
+            .with_known_functions(executor.function_arities())
+            .with_known_types(executor.struct_fields(), executor.type_alias_variants())
+            .check();
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            continue;
+        }
+
+        // Echo the value of a trailing expression, e.g. `p.x` or `add(1, 2)`
+        match executor.exec_repl(ast) {
+            Ok(Some(Value::Nil)) | Ok(None) => {}
+            Ok(Some(value)) => println!("{}", executor.value_to_string(&value)),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+}
+
+fn run_command(command: &str, executor: &mut Executor, history: &History) -> Command {
+    match command {
+        ".help" => println!("{}", HELP),
+        ".vars" => {
+            for line in variable_lines(executor) {
+                println!("{}", line);
+            }
+        }
+        ".clear" => *executor = Executor::new(Ast::new(Vec::new())),
+        ".history" => {
+            for (i, line) in history.lines.iter().enumerate() {
+                println!("{:>4}  {}", i + 1, line);
+            }
+        }
+        ".exit" => return Command::Exit,
+        _ => eprintln!("Unknown command '{}'; .help lists the commands", command),
+    }
+    Command::Continue
+}
+
+// `.vars` output: each variable as `name = value`, sorted by name, with
+// strings quoted so `x = "1"` and `x = 1` can be told apart
+fn variable_lines(executor: &Executor) -> Vec<String> {
+    let mut variables = executor.variables();
+    variables.sort_by(|a, b| a.0.cmp(b.0));
+    variables
+        .into_iter()
+        .map(|(name, value)| {
+            let shown = match value {
+                Value::String(s) => format!("{:?}", s),
+                value => executor.value_to_string(value),
+            };
+            format!("{} = {}", name, shown)
+        })
+        .collect()
+}
+
+// Every line entered, loaded from and appended to the history file so it
+// carries over between sessions
+struct History {
+    lines: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    fn load() -> Self {
+        let path = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        let lines = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        History { lines, path }
+    }
+
+    // A history file that can't be written just means the line is only
+    // remembered for this session
+    fn add(&mut self, line: &str) {
+        self.lines.push(line.to_string());
+        if let Some(path) = &self.path
+            && let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path)
+        {
+            writeln!(file, "{}", line).ok();
+        }
+    }
+}
+
+// More brackets opened than closed means the input continues on the next line
+fn is_incomplete(tokens: &[Spanned]) -> bool {
+    let mut depth: i32 = 0;
+    for spanned in tokens {
+        match spanned.token {
+            Token::LeftParen | Token::LeftBrace | Token::LeftBracket => depth += 1,
+            Token::RightParen | Token::RightBrace | Token::RightBracket => depth -= 1,
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enter(executor: &mut Executor, source: &str) {
+        let tokens = Lexer::new(source.as_bytes().to_vec()).tokenize();
+        let ast = Parser::new(tokens).parse().expect("input parses");
+        executor.exec_repl(ast).expect("input runs");
+    }
+
+    #[test]
+    fn vars_lists_a_definition_until_clear() {
+        let history = History {
+            lines: Vec::new(),
+            path: None,
+        };
+        let mut executor = Executor::new(Ast::new(Vec::new()));
+        enter(&mut executor, "let name = \"ada\"");
+        enter(&mut executor, "let count = 2");
+        assert_eq!(variable_lines(&executor), ["count = 2", "name = \"ada\""]);
+
+        run_command(".clear", &mut executor, &history);
+        assert!(variable_lines(&executor).is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::ast::Type;
+use crate::executor::{Function, Value};
+
+// Variables in nested scopes, innermost last. Blocks, loop iterations, match
+// arms and function calls each push a scope that is dropped when they end,
+// so a `let` inside one doesn't outlive it.
+//
+// A function call starts a frame: inside it only the frame's own scopes and
+// the outermost (global) scope can be read, never the caller's variables.
+#[derive(Debug, Clone)]
+pub struct Scopes {
+    scopes: Vec<Scope>,
+    // Index of the first scope of each active call, innermost last
+    frames: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    values: HashMap<Rc<str>, Value>,
+    // The declared type of each annotated variable
+    types: HashMap<Rc<str>, Type>,
+    // Functions defined by a `fn` inside a function body
+    functions: HashMap<String, Vec<Rc<Function>>>,
+}
+
+impl Scopes {
+    pub fn new() -> Self {
+        Scopes {
+            scopes: vec![Scope::default()],
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    // Drop the innermost scope, handing back what was bound in it
+    pub fn pop(&mut self) -> HashMap<Rc<str>, Value> {
+        debug_assert!(self.scopes.len() > 1, "the outermost scope is never popped");
+        self.scopes.pop().expect("there is always a scope").values
+    }
+
+    // Start a call whose first scope holds `captured`
+    pub fn push_frame(&mut self, captured: &[(Rc<str>, Value)]) {
+        self.frames.push(self.scopes.len());
+        self.push();
+        for (name, value) in captured {
+            self.insert(Rc::clone(name), value.clone());
+        }
+    }
+
+    // End the innermost call, dropping any scopes an error left behind, and
+    // hand back what its first scope held
+    pub fn pop_frame(&mut self) -> HashMap<Rc<str>, Value> {
+        let start = self.frames.pop().expect("a frame was pushed");
+        self.scopes.truncate(start + 1);
+        self.pop()
+    }
+
+    pub fn in_frame(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    // The first scope past the global one that lookups can see
+    fn local_start(&self) -> usize {
+        self.frames.last().map_or(1, |start| *start)
+    }
+
+    // The scopes a lookup can see, innermost first
+    fn reachable(&self) -> impl Iterator<Item = &Scope> {
+        let local = &self.scopes[self.local_start()..];
+        local.iter().rev().chain(&self.scopes[..1])
+    }
+
+    // The nearest binding of `name`, looking outward
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.reachable().find_map(|scope| scope.values.get(name))
+    }
+
+    // A variable found only in the global scope is copied into the call's
+    // first scope before it is changed, so a function never changes it for
+    // its caller
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        let start = self.local_start();
+        let local = self.scopes[start..]
+            .iter()
+            .rposition(|scope| scope.values.contains_key(name));
+        let index = match local {
+            Some(offset) => start + offset,
+            None if self.in_frame() => {
+                let global = &self.scopes[0];
+                let (key, value) = global.values.get_key_value(name)?;
+                let (key, value) = (Rc::clone(key), value.clone());
+                let typ = global.types.get(name).cloned();
+                let base = &mut self.scopes[start];
+                if let Some(typ) = typ {
+                    base.types.insert(Rc::clone(&key), typ);
+                }
+                base.values.insert(key, value);
+                start
+            }
+            None => 0,
+        };
+        self.scopes[index].values.get_mut(name)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // `let` binds in the innermost scope, shadowing any outer variable
+    pub fn insert(&mut self, name: Rc<str>, value: Value) {
+        let innermost = self.scopes.last_mut().expect("there is always a scope");
+        innermost.types.remove(&name);
+        innermost.values.insert(name, value);
+    }
+
+    // Like `insert`, for a variable whose later assignments must keep to `typ`
+    pub fn insert_typed(&mut self, name: Rc<str>, value: Value, typ: Type) {
+        let innermost = self.scopes.last_mut().expect("there is always a scope");
+        innermost.types.insert(Rc::clone(&name), typ);
+        innermost.values.insert(name, value);
+    }
+
+    // The declared type of the nearest binding of `name`, if it had one
+    pub fn declared_type(&self, name: &str) -> Option<&Type> {
+        self.reachable()
+            .find(|scope| scope.values.contains_key(name))
+            .and_then(|scope| scope.types.get(name))
+    }
+
+    // Every name that can currently be read, outer scopes first
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        let mut scopes: Vec<&Scope> = self.reachable().collect();
+        scopes.reverse();
+        scopes
+            .into_iter()
+            .flat_map(|scope| scope.values.keys().map(|name| &**name))
+    }
+
+    // Each variable with the value a lookup would find; shadowed ones are
+    // left out
+    pub fn visible(&self) -> Vec<(&str, &Value)> {
+        let mut seen = HashSet::new();
+        self.reachable()
+            .flat_map(|scope| scope.values.iter())
+            .filter(|(name, _)| seen.insert(*name))
+            .map(|(name, value)| (&**name, value))
+            .collect()
+    }
+
+    // The visible variables outside the global scope, for a function
+    // defined here to take along
+    pub fn locals(&self) -> Vec<(Rc<str>, Value)> {
+        let mut seen = HashSet::new();
+        self.scopes[self.local_start()..]
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.values.iter())
+            .filter(|(name, _)| seen.insert(*name))
+            .map(|(name, value)| (Rc::clone(name), value.clone()))
+            .collect()
+    }
+
+    // Functions defined in a body are visible until the scope that defined
+    // them ends, including to the functions that body calls
+    pub fn function(&self, name: &str) -> Option<&Vec<Rc<Function>>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.functions.get(name))
+    }
+
+    pub fn define_function(&mut self, func: Rc<Function>) {
+        let innermost = self.scopes.last_mut().expect("there is always a scope");
+        let overloads = innermost
+            .functions
+            .entry(func.name().to_string())
+            .or_default();
+        add_overload(overloads, func);
+    }
+}
+
+// Replace a definition taking the same arguments, or add an overload
+// alongside the others
+pub fn add_overload(overloads: &mut Vec<Rc<Function>>, func: Rc<Function>) {
+    overloads.retain(|existing| !existing.same_signature(&func));
+    overloads.push(func);
+}
+
+impl Default for Scopes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,36 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// Runs `source` with the built `weave` binary
+fn run(name: &str, source: &str) -> Output {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("weave-{}-{}.wv", name, std::process::id()));
+    fs::write(&path, source).expect("program is written");
+    let output = Command::new(env!("CARGO_BIN_EXE_weave"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .expect("weave runs");
+    fs::remove_file(&path).ok();
+    output
+}
+
+#[test]
+fn runtime_error_exits_nonzero() {
+    let output = run("runtime-error", "fn f(x: number) { return x }\nf(\"a\")\n");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Runtime error: Type mismatch for parameter 'x'"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn successful_program_exits_zero() {
+    let output = run("ok", "print(1)\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+}